@@ -3,6 +3,7 @@
 //! The AST nodes somewhat reflect the canonical syntax specified in
 //! <https://www.bell-labs.com/usr/dmr/www/kbman.pdf> S2.1, with extensions.
 
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::fmt;
 use std::path::Path;
@@ -18,9 +19,13 @@ use bumpalo::Bump;
 pub struct Span(u32);
 
 impl Span {
+	fn from_index(index: usize) -> Span {
+		Span(index.try_into().expect("ran out of span indices"))
+	}
+
 	/// Returns the byte range for this span.
 	pub fn range(self, ctx: &Context) -> (usize, usize) {
-		ctx.spans.borrow().raw_spans[self.0 as usize].range
+		ctx.spans.borrow()[self.0 as usize].range
 	}
 
 	/// Returns the textual contents of this span as a string slice.
@@ -33,9 +38,22 @@ impl Span {
 	///
 	/// Line and column are zero-indexed; you may want to one-index them for
 	/// pretty-printing.
+	///
+	/// Coordinates are not stored with the span; they are recomputed from the
+	/// context's line index on each call.
 	pub fn coords(self, ctx: &Context) -> (u32, u32) {
-		let raw = &ctx.spans.borrow().raw_spans[self.0 as usize];
-		(raw.line, raw.col)
+		let (start, _) = self.range(ctx);
+		ctx.coords_of(start)
+	}
+
+	/// Returns the coordinates (line and column) just past the end of this
+	/// span, zero-indexed like [`Span::coords()`].
+	///
+	/// For spans that cross lines, such as multi-line block comments or
+	/// function bodies, this is on a later line than the start.
+	pub fn end_coords(self, ctx: &Context) -> (u32, u32) {
+		let (_, end) = self.range(ctx);
+		ctx.coords_of(end)
 	}
 
 	/// See [`Span::coords()`].
@@ -48,6 +66,31 @@ impl Span {
 		self.coords(ctx).1
 	}
 
+	/// Returns the zero-indexed column this span starts at, counted in bytes
+	/// rather than characters.
+	pub fn byte_col_number(self, ctx: &Context) -> u32 {
+		let (start, _) = self.range(ctx);
+		ctx.line_prefix(start).len() as u32
+	}
+
+	/// Returns the zero-indexed column this span starts at, counted in terminal
+	/// cells: wide characters (such as CJK ideographs) count twice, and
+	/// combining marks not at all.
+	///
+	/// This is the column to use when aligning a marker under the span in a
+	/// snippet of the source line.
+	pub fn display_col_number(self, ctx: &Context) -> u32 {
+		let (start, _) = self.range(ctx);
+		ctx.line_prefix(start).chars().map(display_width).sum()
+	}
+
+	/// Returns the whole line this span starts on, without its line
+	/// terminator.
+	pub fn line_text(self, ctx: &Context) -> &str {
+		let (start, _) = self.range(ctx);
+		ctx.line_text(start)
+	}
+
 	/// Uses the given `Context` to produce a [`fmt::Display`]able value.
 	///
 	/// `Span` itself cannot be [`fmt::Display`], because we need a matching
@@ -73,12 +116,44 @@ impl Span {
 	}
 }
 
-/// A position in the source code marking the start of a `Span`.
+/// Returns the number of terminal cells `c` occupies.
+///
+/// This is an approximation of Unicode's East Asian Width property that covers
+/// the common wide and zero-width ranges, without pulling in the full tables.
+pub(crate) fn display_width(c: char) -> u32 {
+	match c {
+		'\u{0300}'..='\u{036F}'
+		| '\u{1AB0}'..='\u{1AFF}'
+		| '\u{1DC0}'..='\u{1DFF}'
+		| '\u{200B}'..='\u{200F}'
+		| '\u{20D0}'..='\u{20FF}'
+		| '\u{FE00}'..='\u{FE0F}'
+		| '\u{FE20}'..='\u{FE2F}' => 0,
+		'\u{1100}'..='\u{115F}'
+		| '\u{2E80}'..='\u{303E}'
+		| '\u{3041}'..='\u{33FF}'
+		| '\u{3400}'..='\u{4DBF}'
+		| '\u{4E00}'..='\u{9FFF}'
+		| '\u{A000}'..='\u{A4CF}'
+		| '\u{AC00}'..='\u{D7A3}'
+		| '\u{F900}'..='\u{FAFF}'
+		| '\u{FE30}'..='\u{FE4F}'
+		| '\u{FF00}'..='\u{FF60}'
+		| '\u{FFE0}'..='\u{FFE6}'
+		| '\u{1F300}'..='\u{1F64F}'
+		| '\u{1F900}'..='\u{1F9FF}'
+		| '\u{20000}'..='\u{2FFFD}'
+		| '\u{30000}'..='\u{3FFFD}' => 2,
+		_ => 1,
+	}
+}
+
+/// A position in the source code, such as the context's cursor.
+///
+/// This is only a byte offset; line and column are derived on demand.
 #[derive(Copy, Clone, Default)]
 pub(crate) struct Mark {
 	offset: usize,
-	line: u32,
-	col: u32,
 }
 
 /// Internal representation of information associated with a span.
@@ -86,18 +161,70 @@ pub(crate) struct Mark {
 /// Currently stored as AoS, but SoA may be a viable future optimization.
 struct RawSpan {
 	range: (usize, usize),
-	line: u32,
-	col: u32,
 }
 
-/// State for generating spans. This is broken out into a separate struct so
-/// that we can wrap it in a RefCell.
-#[derive(Default)]
-struct SpanState {
-	// `Span`s index into this array.
-	raw_spans: Vec<RawSpan>,
-	// The cursor for tracking marks and creating spans.
-	cursor: Mark,
+/// Spans created in bulk, e.g. one for every token, which are only added to
+/// their [`Context`] once they are all done.
+///
+/// This avoids borrowing the context's span table for every single span.
+pub(crate) struct SpanBatch {
+	/// The index of the first span in the batch.
+	base: usize,
+	/// The offset that ranges in the batch are relative to.
+	origin: usize,
+	spans: Vec<RawSpan>,
+}
+
+impl SpanBatch {
+	/// Adds a span covering the bytes `start..end` past the context's cursor
+	/// (as of when the batch was started).
+	pub(crate) fn push(&mut self, start: usize, end: usize) -> Span {
+		self.spans.push(RawSpan {
+			range: (self.origin + start, self.origin + end),
+		});
+		Span::from_index(self.base + self.spans.len() - 1)
+	}
+}
+
+/// Computes the coordinates of a sequence of offsets, each usually no earlier
+/// than the last, such as the starts of every token in order.
+///
+/// This takes time proportional to the distance between consecutive offsets,
+/// whereas [`Span::coords()`] counts every character from the start of the
+/// line, which is quadratic over every token of a long line.
+pub(crate) struct CoordsCursor<'a> {
+	ctx: &'a Context,
+	offset: usize,
+	coords: (u32, u32),
+}
+
+impl<'a> CoordsCursor<'a> {
+	/// Creates a cursor at the start of `ctx`'s source.
+	pub(crate) fn new(ctx: &'a Context) -> Self {
+		Self {
+			ctx,
+			offset: 0,
+			coords: (0, 0),
+		}
+	}
+
+	/// Returns the zero-indexed coordinates that `span` starts at, like
+	/// [`Span::coords()`].
+	pub(crate) fn coords(&mut self, span: Span) -> (u32, u32) {
+		let (offset, _) = span.range(self.ctx);
+		if offset < self.offset {
+			self.coords = self.ctx.coords_of(offset);
+		} else {
+			for c in self.ctx.source[self.offset..offset].chars() {
+				self.coords = match c {
+					'\n' => (self.coords.0 + 1, 0),
+					_ => (self.coords.0, self.coords.1 + 1),
+				};
+			}
+		}
+		self.offset = offset;
+		self.coords
+	}
 }
 
 /// A parsing context.
@@ -107,22 +234,34 @@ struct SpanState {
 pub struct Context {
 	path: PathBuf,
 	source: String,
+	// Byte offsets of the first character of every line, in ascending order.
+	// Always starts with 0. Used to turn offsets into coordinates lazily, so
+	// that advancing the cursor never has to look at the characters it skips.
+	line_starts: Vec<usize>,
 
 	// All AST nodes are allocated on this arena, to avoid hammering the heap and
 	// so that all nodes can simply contain references and slices directly, to
 	// aid pattern-matching.
 	pub(crate) arena: Bump,
-	spans: RefCell<SpanState>,
+	// `Span`s index into this array.
+	spans: RefCell<Vec<RawSpan>>,
+	// The cursor for tracking marks and creating spans.
+	cursor: Cell<Mark>,
 }
 
 impl Context {
 	/// Creates a new parsing context over the given path and source.
 	pub fn new(path: PathBuf, source: String) -> Context {
+		let line_starts = std::iter::once(0)
+			.chain(source.match_indices('\n').map(|(i, _)| i + 1))
+			.collect();
 		Self {
 			path,
 			source,
+			line_starts,
 			arena: Bump::new(),
 			spans: Default::default(),
+			cursor: Default::default(),
 		}
 	}
 
@@ -136,31 +275,91 @@ impl Context {
 		&self.source
 	}
 
+	/// Returns the zero-indexed line containing the given byte offset.
+	fn line_of(&self, offset: usize) -> usize {
+		self.line_starts.partition_point(|&start| start <= offset) - 1
+	}
+
+	/// Converts a byte offset into zero-indexed line and column coordinates.
+	///
+	/// Columns are counted in characters, not bytes.
+	fn coords_of(&self, offset: usize) -> (u32, u32) {
+		let line = self.line_of(offset);
+		let col = self.source[self.line_starts[line]..offset].chars().count();
+		(line as u32, col as u32)
+	}
+
+	/// Returns the text between the start of the line containing `offset` and
+	/// `offset` itself.
+	fn line_prefix(&self, offset: usize) -> &str {
+		&self.source[self.line_starts[self.line_of(offset)]..offset]
+	}
+
+	/// Returns the line containing `offset`, without its line terminator.
+	fn line_text(&self, offset: usize) -> &str {
+		let start = self.line_starts[self.line_of(offset)];
+		let line = self.source[start..].split('\n').next().unwrap_or("");
+		line.strip_suffix('\r').unwrap_or(line)
+	}
+
 	pub(crate) fn unread(&self) -> &str {
-		&self.source[self.spans.borrow().cursor.offset..]
+		&self.source[self.cursor.get().offset..]
 	}
 
-	/// Creates a new mark pointing to the current position in the source.
-	pub(crate) fn mark(&self) -> Mark {
-		self.spans.borrow().cursor
+	/// Starts a batch of new spans, positioned relative to the cursor.
+	///
+	/// No other spans may be created until the batch is handed back to
+	/// [`Context::finish_spans()`], and until then, the spans in it must not be
+	/// queried.
+	pub(crate) fn start_spans(&self) -> SpanBatch {
+		SpanBatch {
+			base: self.spans.borrow().len(),
+			origin: self.cursor.get().offset,
+			spans: Vec::new(),
+		}
 	}
 
-	/// Creates a new span using the given mark as the starting point.
-	pub(crate) fn span(&self, start: Mark) -> Span {
+	/// Adds the spans in `batch` to this context, after which they can be
+	/// queried.
+	///
+	/// # Panics
+	///
+	/// Panics if any other spans were created after the batch was started.
+	pub(crate) fn finish_spans(&self, batch: SpanBatch) {
 		let mut spans = self.spans.borrow_mut();
-		let end = spans.cursor.offset;
-		spans.raw_spans.push(RawSpan {
-			range: (start.offset, end),
-			line: start.line,
-			col: start.col,
-		});
+		assert_eq!(spans.len(), batch.base, "spans created during a batch");
+		spans.extend(batch.spans);
+	}
+
+	/// Creates a new span running from the start of `first` to the end of
+	/// `last`.
+	pub(crate) fn join(&self, first: Span, last: Span) -> Span {
+		let (start, _) = first.range(self);
+		let (_, end) = last.range(self);
+		self.push_span((start, end))
+	}
+
+	/// Creates a new empty span at the given byte offset.
+	pub(crate) fn empty_span(&self, offset: usize) -> Span {
+		assert!(offset <= self.source.len(), "empty span out of bounds");
+		self.push_span((offset, offset))
+	}
 
-		let index: u32 = spans
-			.raw_spans
-			.len()
-			.try_into()
-			.expect("ran out of span indices");
-		Span(index)
+	/// Creates a new span covering the bytes `start..end` of `span`'s text.
+	///
+	/// # Panics
+	///
+	/// Panics if `start..end` is not within `span`.
+	pub(crate) fn subspan(&self, span: Span, start: usize, end: usize) -> Span {
+		let (base, limit) = span.range(self);
+		assert!(start <= end && base + end <= limit, "subspan out of bounds");
+		self.push_span((base + start, base + end))
+	}
+
+	fn push_span(&self, range: (usize, usize)) -> Span {
+		let mut spans = self.spans.borrow_mut();
+		spans.push(RawSpan { range });
+		Span::from_index(spans.len() - 1)
 	}
 
 	/// Advances the cursor.
@@ -173,17 +372,9 @@ impl Context {
 	///
 	/// Panics if `len > self.unread().len()`.
 	pub(crate) fn advance_cursor(&self, len: usize) {
-		let mut spans = self.spans.borrow_mut();
-		let offset = spans.cursor.offset;
-		for c in self.source[offset..offset + len].chars() {
-			if c == '\n' {
-				spans.cursor.line += 1;
-				spans.cursor.col = 0;
-			} else {
-				spans.cursor.col += 1;
-			}
-		}
-		spans.cursor.offset += len;
+		let offset = self.cursor.get().offset + len;
+		assert!(offset <= self.source.len(), "advanced past end of source");
+		self.cursor.set(Mark { offset });
 	}
 }
 
@@ -206,6 +397,10 @@ pub enum Def<'ctx> {
 	Global(Global<'ctx>),
 	/// A function definition.
 	Func(Func<'ctx>),
+	/// A record layout.
+	///
+	/// This is an extension, enabled with `--ext=records`.
+	Record(Record<'ctx>),
 }
 
 /// A global variable.
@@ -258,6 +453,27 @@ pub struct Func<'ctx> {
 	pub span: Span,
 }
 
+/// A record layout: e.g. `rec point { x; y; }`.
+///
+/// Records do not introduce a new type; they only name word offsets into a
+/// vector, so that `p.y` means the same thing as `p[1]`.
+pub struct Record<'ctx> {
+	/// The name of the record.
+	pub name: Id<'ctx>,
+	/// The record's members, in offset order.
+	pub members: &'ctx [Id<'ctx>],
+	/// The overall span.
+	pub span: Span,
+}
+
+impl Record<'_> {
+	/// Returns the word offset of the member with the given name, if this
+	/// record has one.
+	pub fn offset_of(&self, member: &str) -> Option<usize> {
+		self.members.iter().position(|id| id.name == member)
+	}
+}
+
 /// A statement.
 ///
 /// Corresponds to `statement` in the B grammar.
@@ -311,6 +527,28 @@ pub enum StmtKind<'ctx> {
 		/// The loop body.
 		body: &'ctx Stmt<'ctx>,
 	},
+	/// A do-while loop, e.g. `do foo(); while (1 == 1);`.
+	///
+	/// This is an extension, enabled with `--ext=do-while`.
+	DoWhile {
+		/// The loop body, which always runs at least once.
+		body: &'ctx Stmt<'ctx>,
+		/// The condition checked after each iteration.
+		cond: Expr<'ctx>,
+	},
+	/// A C-style for loop, e.g. `for (i = 0; i < n; i++) foo(i);`.
+	///
+	/// This is an extension, enabled with `--ext=for`.
+	For {
+		/// The expression evaluated once before the loop, if any.
+		init: Option<Expr<'ctx>>,
+		/// The loop condition, if any; a missing condition loops forever.
+		cond: Option<Expr<'ctx>>,
+		/// The expression evaluated after each iteration, if any.
+		step: Option<Expr<'ctx>>,
+		/// The loop body.
+		body: &'ctx Stmt<'ctx>,
+	},
 	/// A switch statement: e.g. `switch (1) case 1: 5;`.
 	Switch {
 		/// The value being switched on.
@@ -323,10 +561,24 @@ pub enum StmtKind<'ctx> {
 	Goto(Expr<'ctx>),
 	/// A return statement: e.g. `return (something);`.
 	Return(Option<Expr<'ctx>>),
+	/// An inline assembly statement: e.g. `__asm("syscall", n, buf);`.
+	///
+	/// This is an extension, enabled with `--ext=asm`.
+	Asm {
+		/// The raw assembly text, spliced verbatim into the backend's output.
+		code: Str<'ctx>,
+		/// B names bound as operands of the assembly text, in order.
+		operands: &'ctx [Id<'ctx>],
+	},
 	/// A plain expression at statement scope: e.g. `foo;`.
 	Expr(Expr<'ctx>),
 	/// An empty statement: `;`.
 	Empty,
+	/// A statement that failed to parse.
+	///
+	/// The parser produces these while recovering from a syntax error, after
+	/// diagnosing it, so that the rest of the function can still be analyzed.
+	Error,
 }
 
 /// An expression.
@@ -360,6 +612,16 @@ pub enum ExprKind<'ctx> {
 		/// The indexing value.
 		index: &'ctx Expr<'ctx>,
 	},
+	/// A record member access, e.g. `p.x`.
+	///
+	/// This is an extension, enabled with `--ext=records`; it is equivalent to
+	/// indexing `ptr` with the member's offset.
+	Member {
+		/// The "pointer" value being offset and dereferenced.
+		ptr: &'ctx Expr<'ctx>,
+		/// The name of the member.
+		member: Id<'ctx>,
+	},
 	/// A plain assignment, e.g. `x = y`
 	Assign {
 		/// The left-hand side.
@@ -405,9 +667,17 @@ pub enum ExprKind<'ctx> {
 		/// The arguments to pass into the call.
 		args: &'ctx [&'ctx Expr<'ctx>],
 	},
+	/// An expression that failed to parse.
+	///
+	/// Like [`StmtKind::Error`], this is a placeholder produced during error
+	/// recovery, e.g. for the missing operand in `x = ;`. Its span covers the
+	/// offending token if one was consumed (such as a constant that is too
+	/// large), and is otherwise empty.
+	Error,
 }
 
 /// An unary operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum UnaryOp {
 	/// `++x`.
 	PreInc,
@@ -421,8 +691,12 @@ pub enum UnaryOp {
 	Minus,
 	/// `!x`.
 	Not,
+	/// `&x`.
+	AddrOf,
 }
 
+/// A binary operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BinaryOp {
 	/// `x | y`.
 	Or,
@@ -475,9 +749,11 @@ pub struct Int {
 }
 
 /// A character constant.
-pub struct Char {
-	/// The value of the constant.
-	pub value: u8,
+///
+/// B packs as many characters as fit in a word into one constant, e.g. `'ab'`.
+pub struct Char<'ctx> {
+	/// The characters of the constant.
+	pub value: &'ctx str,
 	/// The constant's span.
 	pub span: Span,
 }
@@ -497,7 +773,56 @@ pub enum Const<'ctx> {
 	/// An integer constant.
 	Int(Int),
 	/// A character constant.
-	Char(Char),
+	Char(Char<'ctx>),
 	/// A string constant.
 	Str(Str<'ctx>),
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lex;
+	use crate::CompilationConfiguration;
+
+	/// Lexes `source`, returning its context and tokens.
+	fn lex_source(source: &str) -> (Context, lex::TokenList) {
+		let config = CompilationConfiguration::for_test(source);
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let tokens = lex::lex(&ctx, &config, &mut Vec::new());
+		(ctx, tokens)
+	}
+
+	#[test]
+	fn coords_after_block_comments() {
+		let (ctx, tokens) = lex_source("/**/y/*\n*/2;\n/* a\n\nb */ z");
+		let coords: Vec<_> = tokens
+			.tokens
+			.iter()
+			.map(|token| (token.span.text(&ctx), token.span.coords(&ctx)))
+			.collect();
+		assert_eq!(
+			coords,
+			[("y", (0, 4)), ("2", (1, 2)), (";", (1, 3)), ("z", (4, 5))]
+		);
+
+		let mut cursor = CoordsCursor::new(&ctx);
+		for token in &tokens.tokens {
+			assert_eq!(cursor.coords(token.span), token.span.coords(&ctx));
+		}
+	}
+
+	#[test]
+	fn end_coords() {
+		let (ctx, tokens) = lex_source("f() {\n\tx;\n}\ny");
+		let [f, .., brace, _] = tokens.tokens[..] else {
+			panic!("wrong number of tokens");
+		};
+		assert_eq!(f.span.end_coords(&ctx), (0, 1));
+		assert_eq!(brace.span.end_coords(&ctx), (2, 1));
+
+		let body = ctx.join(f.span, brace.span);
+		assert_eq!(body.coords(&ctx), (0, 0));
+		assert_eq!(body.end_coords(&ctx), (2, 1));
+		assert_eq!(body.text(&ctx), "f() {\n\tx;\n}");
+	}
+}
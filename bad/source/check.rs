@@ -0,0 +1,174 @@
+//! Checks on the meaning of a parsed program, beyond its syntax.
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+
+/// Checks the declarations in each function of `program`, warning about names
+/// that are declared both `extrn` and `auto`, and about `extrn` declarations
+/// of a parameter.
+///
+/// B accepts both, and silently uses one of the declarations for the whole
+/// function, which is rarely what was meant.
+pub fn check_declarations(
+	program: &Program,
+	diagnostics: &mut Vec<Diagnostic>,
+) {
+	for def in program.defs {
+		let Def::Func(func) = def else { continue };
+		let mut checker = DeclChecker {
+			params: func.params.iter().map(|id| (id.name, id.span)).collect(),
+			autos: HashMap::new(),
+			extrns: HashMap::new(),
+			diagnostics,
+		};
+		for stmt in func.body {
+			checker.stmt(stmt);
+		}
+	}
+}
+
+struct DeclChecker<'a, 'ctx> {
+	// The first declaration of each name, of each kind, in the function being
+	// checked. Declarations apply to the whole function, not just the block
+	// they appear in.
+	params: HashMap<&'ctx str, Span>,
+	autos: HashMap<&'ctx str, Span>,
+	extrns: HashMap<&'ctx str, Span>,
+	diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'ctx> DeclChecker<'_, 'ctx> {
+	fn stmt(&mut self, stmt: &Stmt<'ctx>) {
+		match &stmt.kind {
+			StmtKind::Auto { decls } => {
+				for (id, _) in *decls {
+					if let Some(&extrn) = self.extrns.get(id.name) {
+						self.conflict(id, extrn, "`extrn`");
+					}
+					self.autos.entry(id.name).or_insert(id.span);
+				}
+			}
+			StmtKind::Extrn { decls } => {
+				for id in *decls {
+					if let Some(&param) = self.params.get(id.name) {
+						self.diagnostics.push(
+							Diagnostic::warning(
+								id.span,
+								format!(
+									"`extrn` declaration of `{}` shadows a \
+									 parameter",
+									id.name
+								),
+							)
+							.with_note(param, "the parameter is declared here"),
+						);
+					}
+					if let Some(&auto) = self.autos.get(id.name) {
+						self.conflict(id, auto, "`auto`");
+					}
+					self.extrns.entry(id.name).or_insert(id.span);
+				}
+			}
+			StmtKind::Block(stmts) => {
+				for stmt in *stmts {
+					self.stmt(stmt);
+				}
+			}
+			StmtKind::If { body, elze, .. } => {
+				self.stmt(body);
+				if let Some(elze) = elze {
+					self.stmt(elze);
+				}
+			}
+			StmtKind::While { body, .. }
+			| StmtKind::DoWhile { body, .. }
+			| StmtKind::For { body, .. }
+			| StmtKind::Switch { body, .. } => self.stmt(body),
+			StmtKind::Label(_)
+			| StmtKind::Case(_)
+			| StmtKind::Goto(_)
+			| StmtKind::Return(_)
+			| StmtKind::Asm { .. }
+			| StmtKind::Expr(_)
+			| StmtKind::Empty
+			| StmtKind::Error => {}
+		}
+	}
+
+	/// Reports that `id` was already declared as `other`, at `prev`.
+	fn conflict(&mut self, id: &Id, prev: Span, other: &str) {
+		self.diagnostics.push(
+			Diagnostic::warning(
+				id.span,
+				format!("`{}` is declared both `extrn` and `auto`", id.name),
+			)
+			.with_note(
+				prev,
+				format!("`{}` is declared {} here", id.name, other),
+			),
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::compile;
+	use crate::CompilationConfiguration;
+
+	/// Compiles `source`, and returns every diagnostic rendered with its notes.
+	fn check(source: &str) -> Vec<String> {
+		let result =
+			compile(&CompilationConfiguration::for_test(source)).unwrap();
+		result
+			.diagnostics
+			.iter()
+			.map(|d| d.display(&result.context).to_string())
+			.collect()
+	}
+
+	#[test]
+	fn extrn_and_auto() {
+		assert_eq!(
+			check("f() {\n\tauto x;\n\tif (1) {\n\t\textrn x, y;\n\t}\n}\n"),
+			["\
+test.b[4:9]: warning: `x` is declared both `extrn` and `auto`
+4 | \t\textrn x, y;
+  | \t\t      ^
+test.b[2:7]: note: `x` is declared `auto` here
+2 | \tauto x;
+  | \t     ^"]
+		);
+		assert_eq!(
+			check("f() { extrn x; auto y, x; }"),
+			["\
+test.b[1:24]: warning: `x` is declared both `extrn` and `auto`
+1 | f() { extrn x; auto y, x; }
+  |                        ^
+test.b[1:13]: note: `x` is declared `extrn` here
+1 | f() { extrn x; auto y, x; }
+  |             ^"]
+		);
+	}
+
+	#[test]
+	fn extrn_shadowing_a_parameter() {
+		assert_eq!(
+			check("f(a, b) { extrn b; }"),
+			["\
+test.b[1:17]: warning: `extrn` declaration of `b` shadows a parameter
+1 | f(a, b) { extrn b; }
+  |                 ^
+test.b[1:6]: note: the parameter is declared here
+1 | f(a, b) { extrn b; }
+  |      ^"]
+		);
+	}
+
+	#[test]
+	fn separate_functions() {
+		let source = "f(a) { auto x; } g() { extrn a, x; } h() { auto a; }";
+		assert_eq!(check(source), Vec::<String>::new());
+	}
+}
@@ -0,0 +1,240 @@
+//! Errors and warnings produced while compiling.
+
+use std::fmt;
+
+use crate::ast::display_width;
+use crate::ast::Context;
+use crate::ast::Span;
+
+/// How serious a diagnostic is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Level {
+	/// The program is invalid.
+	Error,
+	/// The program is valid, but probably not what was intended, or not
+	/// portable.
+	Warning,
+}
+
+impl fmt::Display for Level {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Level::Error => f.write_str("error"),
+			Level::Warning => f.write_str("warning"),
+		}
+	}
+}
+
+/// A message about a particular span of the source.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+	/// How serious this diagnostic is.
+	pub level: Level,
+	/// The span the diagnostic is about.
+	pub span: Span,
+	/// The message to show the user.
+	pub message: String,
+	/// Related spans that help explain the diagnostic, such as an earlier
+	/// conflicting declaration.
+	pub notes: Vec<Note>,
+}
+
+/// Extra information attached to a [`Diagnostic`], about another span.
+#[derive(Clone, Debug)]
+pub struct Note {
+	/// The span the note is about.
+	pub span: Span,
+	/// The message to show the user.
+	pub message: String,
+}
+
+impl Diagnostic {
+	/// Creates a new error.
+	pub fn error(span: Span, message: impl Into<String>) -> Self {
+		Self {
+			level: Level::Error,
+			span,
+			message: message.into(),
+			notes: Vec::new(),
+		}
+	}
+
+	/// Creates a new warning.
+	pub fn warning(span: Span, message: impl Into<String>) -> Self {
+		Self {
+			level: Level::Warning,
+			span,
+			message: message.into(),
+			notes: Vec::new(),
+		}
+	}
+
+	/// Creates a warning that `what` is not part of strict 1969 B.
+	pub fn extension(span: Span, what: &str) -> Self {
+		Self::warning(span, format!("{} are an extension to B", what))
+	}
+
+	/// Adds a note about `span` to this diagnostic.
+	pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Self {
+		self.notes.push(Note {
+			span,
+			message: message.into(),
+		});
+		self
+	}
+
+	/// Uses the given `Context` to produce a [`fmt::Display`]able value, in the
+	/// form `path[line:col]: level: message`, followed by the source line with
+	/// the span marked beneath it, and then each note in the same form.
+	///
+	/// The column is counted in characters. If the line before the span has
+	/// multi-byte or wide characters, so that the byte and display columns are
+	/// different, those are given too, as `path[line:col] (byte column b,
+	/// display column d)`.
+	pub fn display<'a>(&'a self, ctx: &'a Context) -> impl fmt::Display + 'a {
+		struct Displayable<'a> {
+			diagnostic: &'a Diagnostic,
+			ctx: &'a Context,
+		}
+		impl fmt::Display for Displayable<'_> {
+			fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				let diagnostic = self.diagnostic;
+				write_snippet(
+					f,
+					self.ctx,
+					diagnostic.span,
+					diagnostic.level,
+					&diagnostic.message,
+				)?;
+				for note in &diagnostic.notes {
+					writeln!(f)?;
+					write_snippet(
+						f,
+						self.ctx,
+						note.span,
+						"note",
+						&note.message,
+					)?;
+				}
+				Ok(())
+			}
+		}
+		Displayable {
+			diagnostic: self,
+			ctx,
+		}
+	}
+}
+
+/// Writes one message about `span` for [`Diagnostic::display()`], without a
+/// trailing newline.
+fn write_snippet(
+	f: &mut fmt::Formatter,
+	ctx: &Context,
+	span: Span,
+	level: impl fmt::Display,
+	message: &str,
+) -> fmt::Result {
+	let col = span.col_number(ctx);
+	let byte_col = span.byte_col_number(ctx);
+	let display_col = span.display_col_number(ctx);
+	write!(f, "{}", span.display(ctx))?;
+	if byte_col != col || display_col != col {
+		write!(
+			f,
+			" (byte column {}, display column {})",
+			byte_col + 1,
+			display_col + 1
+		)?;
+	}
+	writeln!(f, ": {}: {}", level, message)?;
+
+	let line = span.line_text(ctx);
+	let number = (span.line_number(ctx) + 1).to_string();
+	writeln!(f, "{} | {}", number, line)?;
+	write!(f, "{:1$} | ", "", number.len())?;
+	// Copy tabs, so that the marker lines up however wide the terminal draws
+	// them.
+	for c in line[..byte_col as usize].chars() {
+		match c {
+			'\t' => f.write_str("\t")?,
+			_ => write!(f, "{:1$}", "", display_width(c) as usize)?,
+		}
+	}
+	let text = span.text(ctx).split('\n').next().unwrap_or("");
+	let width = text.chars().map(display_width).sum::<u32>().max(1);
+	write!(f, "{}", "^".repeat(width as usize))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::ast::Context;
+	use crate::lex;
+	use crate::CompilationConfiguration;
+
+	/// Lexes `source`, and returns the rendering of its first diagnostic.
+	fn render(source: &str) -> String {
+		let config = CompilationConfiguration::for_test(source);
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let mut diagnostics = Vec::new();
+		lex::lex(&ctx, &config, &mut diagnostics);
+		let rendered = diagnostics[0].display(&ctx).to_string();
+		rendered
+	}
+
+	#[test]
+	fn ascii() {
+		assert_eq!(
+			render("main() {\n\tx = @;\n}\n"),
+			"\
+test.b[2:6]: error: unrecognized token `@`
+2 | \tx = @;
+  | \t    ^"
+		);
+	}
+
+	#[test]
+	fn wide_characters() {
+		assert_eq!(
+			render("s = \"日本\"; @"),
+			"\
+test.b[1:11] (byte column 15, display column 13): error: unrecognized token `@`
+1 | s = \"日本\"; @
+  |             ^"
+		);
+	}
+
+	#[test]
+	fn combining_marks() {
+		assert_eq!(
+			render("s = \"e\u{301}\"; @"),
+			"\
+test.b[1:11] (byte column 12, display column 10): error: unrecognized token `@`
+1 | s = \"e\u{301}\"; @
+  |          ^"
+		);
+	}
+
+	#[test]
+	fn multi_byte_characters() {
+		// `é` and `ü` are two bytes each, but one cell wide.
+		assert_eq!(
+			render("/* é */ x = 'ü' @"),
+			"\
+test.b[1:17] (byte column 19, display column 17): error: unrecognized token `@`
+1 | /* é */ x = 'ü' @
+  |                 ^"
+		);
+	}
+
+	#[test]
+	fn wide_spans() {
+		assert_eq!(
+			render("x = \"漢字"),
+			"\
+test.b[1:5]: error: unterminated string literal
+1 | x = \"漢字
+  |     ^^^^^"
+		);
+	}
+}
@@ -0,0 +1,356 @@
+//! Human-readable dumps of compiler data structures.
+//!
+//! These are intended for debugging the compiler itself; the formats are not
+//! stable.
+
+use std::io;
+use std::io::Write;
+
+use crate::ast::*;
+use crate::lex::TokenList;
+
+/// Writes `tokens` to `out`, one per line, as the token's name, its category,
+/// its source text, and the (one-indexed) line and column it starts at.
+pub fn dump_tokens(
+	tokens: &TokenList,
+	ctx: &Context,
+	out: &mut dyn Write,
+) -> io::Result<()> {
+	let mut coords = CoordsCursor::new(ctx);
+	for token in &tokens.tokens {
+		let (line, col) = coords.coords(token.span);
+		writeln!(
+			out,
+			"{:?} ({:?}) {:?} [{}:{}]",
+			token.name,
+			token.name.categorize(),
+			token.span.text(ctx),
+			line + 1,
+			col + 1
+		)?;
+	}
+	Ok(())
+}
+
+/// Writes an indented tree representation of `program` to `out`.
+///
+/// Each node is printed on its own line as its kind, its most important
+/// fields, and the (one-indexed) line and column it starts at; children are
+/// indented one tab beneath their parent.
+pub fn dump_ast(
+	program: &Program,
+	ctx: &Context,
+	out: &mut dyn Write,
+) -> io::Result<()> {
+	let mut printer = AstPrinter {
+		coords: CoordsCursor::new(ctx),
+		out,
+		depth: 0,
+	};
+	writeln!(printer.out, "Program {}", ctx.path().display())?;
+	printer.nested(|p| {
+		for def in program.defs {
+			p.def(def)?;
+		}
+		Ok(())
+	})
+}
+
+struct AstPrinter<'a> {
+	coords: CoordsCursor<'a>,
+	out: &'a mut dyn Write,
+	depth: usize,
+}
+
+impl AstPrinter<'_> {
+	/// Prints a single line at the current depth, followed by the span's
+	/// coordinates.
+	fn line(
+		&mut self,
+		span: Span,
+		text: std::fmt::Arguments,
+	) -> io::Result<()> {
+		let (line, col) = self.coords.coords(span);
+		for _ in 0..self.depth {
+			write!(self.out, "\t")?;
+		}
+		writeln!(self.out, "{} [{}:{}]", text, line + 1, col + 1)
+	}
+
+	/// Runs `body` with the depth increased by one.
+	fn nested(
+		&mut self,
+		body: impl FnOnce(&mut Self) -> io::Result<()>,
+	) -> io::Result<()> {
+		self.depth += 1;
+		let result = body(self);
+		self.depth -= 1;
+		result
+	}
+
+	fn def(&mut self, def: &Def) -> io::Result<()> {
+		match def {
+			Def::Global(global) => {
+				self.line(
+					global.span,
+					format_args!("Global {}", global.name.name),
+				)?;
+				self.nested(|p| {
+					match &global.size {
+						Some((ArraySize::Implicit, span)) => {
+							p.line(*span, format_args!("Size implicit"))?
+						}
+						Some((ArraySize::Explicit(c), span)) => {
+							p.line(*span, format_args!("Size"))?;
+							p.nested(|p| p.constant(c))?;
+						}
+						None => {}
+					}
+					for init in global.inits {
+						p.init_val(init)?;
+					}
+					Ok(())
+				})
+			}
+			Def::Func(func) => {
+				self.line(func.span, format_args!("Func {}", func.name.name))?;
+				self.nested(|p| {
+					for param in func.params {
+						p.id("Param", param)?;
+					}
+					for stmt in func.body {
+						p.stmt(stmt)?;
+					}
+					Ok(())
+				})
+			}
+			Def::Record(record) => {
+				self.line(
+					record.span,
+					format_args!("Record {}", record.name.name),
+				)?;
+				self.nested(|p| {
+					for member in record.members {
+						p.id("Member", member)?;
+					}
+					Ok(())
+				})
+			}
+		}
+	}
+
+	fn id(&mut self, kind: &str, id: &Id) -> io::Result<()> {
+		self.line(id.span, format_args!("{} {}", kind, id.name))
+	}
+
+	fn init_val(&mut self, init: &InitVal) -> io::Result<()> {
+		match init {
+			InitVal::Id(id) => self.id("Id", id),
+			InitVal::Const(c) => self.constant(c),
+		}
+	}
+
+	fn constant(&mut self, c: &Const) -> io::Result<()> {
+		match c {
+			Const::Int(int) => {
+				self.line(int.span, format_args!("Int {}", int.value))
+			}
+			Const::Char(chr) => {
+				self.line(chr.span, format_args!("Char {:?}", chr.value))
+			}
+			Const::Str(str) => {
+				self.line(str.span, format_args!("Str {:?}", str.value))
+			}
+		}
+	}
+
+	fn stmt(&mut self, stmt: &Stmt) -> io::Result<()> {
+		let span = stmt.span;
+		match &stmt.kind {
+			StmtKind::Auto { decls } => {
+				self.line(span, format_args!("Auto"))?;
+				self.nested(|p| {
+					for (id, init) in *decls {
+						p.id("Decl", id)?;
+						if let Some(c) = init {
+							p.nested(|p| p.constant(c))?;
+						}
+					}
+					Ok(())
+				})
+			}
+			StmtKind::Extrn { decls } => {
+				self.line(span, format_args!("Extrn"))?;
+				self.nested(|p| {
+					for id in *decls {
+						p.id("Decl", id)?;
+					}
+					Ok(())
+				})
+			}
+			StmtKind::Label(id) => {
+				self.line(span, format_args!("Label {}", id.name))
+			}
+			StmtKind::Case(c) => {
+				self.line(span, format_args!("Case"))?;
+				self.nested(|p| p.constant(c))
+			}
+			StmtKind::Block(stmts) => {
+				self.line(span, format_args!("Block"))?;
+				self.nested(|p| {
+					for stmt in *stmts {
+						p.stmt(stmt)?;
+					}
+					Ok(())
+				})
+			}
+			StmtKind::If { cond, body, elze } => {
+				self.line(span, format_args!("If"))?;
+				self.nested(|p| {
+					p.expr(cond)?;
+					p.stmt(body)?;
+					if let Some(elze) = elze {
+						p.stmt(elze)?;
+					}
+					Ok(())
+				})
+			}
+			StmtKind::While { cond, body } => {
+				self.line(span, format_args!("While"))?;
+				self.nested(|p| {
+					p.expr(cond)?;
+					p.stmt(body)
+				})
+			}
+			StmtKind::DoWhile { body, cond } => {
+				self.line(span, format_args!("DoWhile"))?;
+				self.nested(|p| {
+					p.stmt(body)?;
+					p.expr(cond)
+				})
+			}
+			StmtKind::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				self.line(span, format_args!("For"))?;
+				self.nested(|p| {
+					// Label each part, since any of them may be missing.
+					let parts =
+						[("Init", init), ("Cond", cond), ("Step", step)];
+					for (label, expr) in parts {
+						if let Some(expr) = expr {
+							p.line(expr.span, format_args!("{}", label))?;
+							p.nested(|p| p.expr(expr))?;
+						}
+					}
+					p.stmt(body)
+				})
+			}
+			StmtKind::Switch { switchee, body } => {
+				self.line(span, format_args!("Switch"))?;
+				self.nested(|p| {
+					p.expr(switchee)?;
+					p.stmt(body)
+				})
+			}
+			StmtKind::Goto(expr) => {
+				self.line(span, format_args!("Goto"))?;
+				self.nested(|p| p.expr(expr))
+			}
+			StmtKind::Return(expr) => {
+				self.line(span, format_args!("Return"))?;
+				match expr {
+					Some(expr) => self.nested(|p| p.expr(expr)),
+					None => Ok(()),
+				}
+			}
+			StmtKind::Asm { code, operands } => {
+				self.line(span, format_args!("Asm {:?}", code.value))?;
+				self.nested(|p| {
+					for id in *operands {
+						p.id("Operand", id)?;
+					}
+					Ok(())
+				})
+			}
+			StmtKind::Expr(expr) => {
+				self.line(span, format_args!("Expr"))?;
+				self.nested(|p| p.expr(expr))
+			}
+			StmtKind::Empty => self.line(span, format_args!("Empty")),
+			StmtKind::Error => self.line(span, format_args!("Error")),
+		}
+	}
+
+	fn expr(&mut self, expr: &Expr) -> io::Result<()> {
+		let span = expr.span;
+		match &expr.kind {
+			ExprKind::Parens(inner) => {
+				self.line(span, format_args!("Parens"))?;
+				self.nested(|p| p.expr(inner))
+			}
+			ExprKind::InitVal(init) => self.init_val(init),
+			ExprKind::Deref { ptr } => {
+				self.line(span, format_args!("Deref"))?;
+				self.nested(|p| p.expr(ptr))
+			}
+			ExprKind::Index { ptr, index } => {
+				self.line(span, format_args!("Index"))?;
+				self.nested(|p| {
+					p.expr(ptr)?;
+					p.expr(index)
+				})
+			}
+			ExprKind::Member { ptr, member } => {
+				self.line(span, format_args!("Member {}", member.name))?;
+				self.nested(|p| p.expr(ptr))
+			}
+			ExprKind::Assign { lhs, rhs } => {
+				self.line(span, format_args!("Assign"))?;
+				self.nested(|p| {
+					p.expr(lhs)?;
+					p.expr(rhs)
+				})
+			}
+			ExprKind::Unary { expr, kind } => {
+				self.line(span, format_args!("Unary {:?}", kind))?;
+				self.nested(|p| p.expr(expr))
+			}
+			ExprKind::Binary {
+				lhs,
+				rhs,
+				kind,
+				is_assign,
+			} => {
+				let assign = if *is_assign { " assign" } else { "" };
+				self.line(span, format_args!("Binary {:?}{}", kind, assign))?;
+				self.nested(|p| {
+					p.expr(lhs)?;
+					p.expr(rhs)
+				})
+			}
+			ExprKind::Ternary { cond, yes, no } => {
+				self.line(span, format_args!("Ternary"))?;
+				self.nested(|p| {
+					p.expr(cond)?;
+					p.expr(yes)?;
+					p.expr(no)
+				})
+			}
+			ExprKind::Call { func, args } => {
+				self.line(span, format_args!("Call"))?;
+				self.nested(|p| {
+					p.expr(func)?;
+					for arg in *args {
+						p.expr(arg)?;
+					}
+					Ok(())
+				})
+			}
+			ExprKind::Error => self.line(span, format_args!("Error")),
+		}
+	}
+}
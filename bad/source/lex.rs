@@ -0,0 +1,681 @@
+//! The lexer, which splits source text into a [`TokenList`].
+
+use std::fmt;
+
+use logos::Lexer;
+use logos::Logos;
+
+use crate::ast::Context;
+use crate::ast::Span;
+use crate::diagnostic::Diagnostic;
+use crate::CompilationConfiguration;
+use crate::EscapeDialect;
+use crate::Extension;
+
+/// The kind of a token.
+///
+/// Tokens carry no payload; their text can be recovered from their span.
+#[derive(Logos, Copy, Clone, Debug, PartialEq, Eq)]
+#[logos(extras = EscapeDialect)]
+#[logos(error = LexError)]
+#[logos(skip r"[ \t\r\n\f]+")]
+#[logos(skip r"/\*([^*]|\*+[^*/])*\*+/")]
+pub enum TokenName {
+	#[token("(")]
+	LeftParen,
+	#[token(")")]
+	RightParen,
+	#[token("{")]
+	LeftBrace,
+	#[token("}")]
+	RightBrace,
+	#[token("[")]
+	LeftBracket,
+	#[token("]")]
+	RightBracket,
+	#[token(";")]
+	Semicolon,
+	#[token(",")]
+	Comma,
+	#[token(".")]
+	Dot,
+	#[token(":")]
+	Colon,
+
+	#[token("=")]
+	Assign,
+	#[token("+")]
+	Plus,
+	#[token("-")]
+	Minus,
+	#[token("*")]
+	Star,
+	#[token("/")]
+	Slash,
+	#[token("%")]
+	Percent,
+	#[token("&")]
+	Ampersand,
+	#[token("|")]
+	Pipe,
+	#[token("!")]
+	Bang,
+	#[token("==")]
+	Equal,
+	#[token("!=")]
+	NotEqual,
+	#[token("<")]
+	Less,
+	#[token("<=")]
+	LessEqual,
+	#[token(">")]
+	Greater,
+	#[token(">=")]
+	GreaterEqual,
+	#[token("<<")]
+	ShiftLeft,
+	#[token(">>")]
+	ShiftRight,
+	#[token("++")]
+	Increment,
+	#[token("--")]
+	Decrement,
+	#[token("?")]
+	Question,
+
+	/// A compound assignment in the B spelling, e.g. `=+`.
+	///
+	/// Note that this means `x=-1` assigns `x - 1` to `x`, as in the B
+	/// reference.
+	#[token("=+")]
+	AssignPlus,
+	#[token("=-")]
+	AssignMinus,
+	#[token("=*")]
+	AssignStar,
+	#[token("=/")]
+	AssignSlash,
+	#[token("=%")]
+	AssignPercent,
+	#[token("=&")]
+	AssignAmpersand,
+	#[token("=|")]
+	AssignPipe,
+	#[token("===")]
+	AssignEqual,
+	#[token("=!=")]
+	AssignNotEqual,
+	#[token("=<")]
+	AssignLess,
+	#[token("=<=")]
+	AssignLessEqual,
+	#[token("=>")]
+	AssignGreater,
+	#[token("=>=")]
+	AssignGreaterEqual,
+	#[token("=<<")]
+	AssignShiftLeft,
+	#[token("=>>")]
+	AssignShiftRight,
+	/// A compound assignment in the C spelling, e.g. `+=`, which is diagnosed
+	/// as an extension in strict mode.
+	#[token("+=")]
+	PlusAssign,
+	#[token("-=")]
+	MinusAssign,
+	#[token("*=")]
+	StarAssign,
+	#[token("/=")]
+	SlashAssign,
+	#[token("%=")]
+	PercentAssign,
+	#[token("&=")]
+	AmpersandAssign,
+	#[token("|=")]
+	PipeAssign,
+	#[token("<<=")]
+	ShiftLeftAssign,
+	#[token(">>=")]
+	ShiftRightAssign,
+
+	#[token("auto")]
+	Auto,
+	#[token("extrn")]
+	Extrn,
+	#[token("if")]
+	If,
+	#[token("else")]
+	Else,
+	#[token("while")]
+	While,
+	#[token("switch")]
+	Switch,
+	#[token("case")]
+	Case,
+	#[token("goto")]
+	Goto,
+	#[token("return")]
+	Return,
+	/// Only a keyword with `--ext=for`; otherwise lexed as an identifier.
+	#[token("for")]
+	For,
+	/// Only a keyword with `--ext=do-while`; otherwise lexed as an identifier.
+	#[token("do")]
+	Do,
+	/// Only a keyword with `--ext=records`; otherwise lexed as an identifier.
+	#[token("rec")]
+	Rec,
+	/// Only a keyword with `--ext=asm`; otherwise lexed as an identifier.
+	#[token("__asm")]
+	Asm,
+
+	#[regex("[A-Za-z_][A-Za-z0-9_]*")]
+	Identifier,
+	/// A decimal integer constant.
+	#[regex("0|[1-9][0-9]*")]
+	Number,
+	/// An octal integer constant: any integer constant with a leading `0`.
+	///
+	/// Per the B reference, the digits `8` and `9` are allowed too, and have
+	/// their usual values; e.g. `09` is `011`.
+	#[regex("0[0-9]+")]
+	OctalNumber,
+	/// A hexadecimal integer constant, e.g. `0x1F`, which is only valid with
+	/// `--ext=modern-literals`.
+	#[regex("0[xX][0-9A-Fa-f]+")]
+	HexNumber,
+	/// A binary integer constant, e.g. `0b101`, which is only valid with
+	/// `--ext=modern-literals`.
+	#[regex("0[bB][01]+")]
+	BinaryNumber,
+	/// A double-quoted string literal, e.g. `"hello*n"`.
+	#[token("\"", |lex| quoted(lex, '"'))]
+	String,
+	/// A single-quoted character constant, e.g. `'a'` or `'ab'`.
+	#[token("'", |lex| quoted(lex, '\''))]
+	Char,
+
+	/// A `//` comment. These never appear in a [`TokenList`].
+	#[regex("//[^\n]*", allow_greedy = true)]
+	LineComment,
+
+	/// Text that could not be lexed, which has already been diagnosed.
+	///
+	/// A `/*` only matches here if it has no closing `*/`, since a whole block
+	/// comment is longer and is skipped instead.
+	#[token("/*", unterminated_comment)]
+	Error,
+}
+
+/// Why some text could not be lexed as a token.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LexError {
+	/// The text does not begin any token.
+	#[default]
+	Unrecognized,
+	/// A string literal is missing its closing quote.
+	UnterminatedString,
+	/// A character constant is missing its closing quote.
+	UnterminatedChar,
+	/// A block comment is missing its closing `*/`.
+	UnterminatedComment,
+}
+
+/// Finishes lexing a string or character literal, whose opening `quote` has
+/// just been lexed.
+///
+/// Escape sequences are skipped over, so that an escaped quote does not end
+/// the literal; they are decoded later, by the parser. Literals may not span
+/// multiple lines.
+fn quoted(lex: &mut Lexer<TokenName>, quote: char) -> Result<(), LexError> {
+	let is_escape = |c| match lex.extras {
+		EscapeDialect::Star => c == '*',
+		EscapeDialect::Backslash => c == '\\',
+		EscapeDialect::Both => c == '*' || c == '\\',
+	};
+	let rest = lex.remainder();
+	let mut chars = rest.char_indices();
+	let end = loop {
+		match chars.next() {
+			None => break rest.len(),
+			Some((i, '\n')) => break i,
+			Some((i, c)) if c == quote => {
+				lex.bump(i + 1);
+				return Ok(());
+			}
+			Some((_, c)) if is_escape(c) => {
+				if chars.clone().next().is_some_and(|(_, c)| c != '\n') {
+					chars.next();
+				}
+			}
+			Some(_) => {}
+		}
+	};
+	lex.bump(end);
+	match quote {
+		'"' => Err(LexError::UnterminatedString),
+		_ => Err(LexError::UnterminatedChar),
+	}
+}
+
+/// Finishes lexing a `/*` that is never closed, which swallows the rest of the
+/// input.
+fn unterminated_comment(lex: &mut Lexer<TokenName>) -> Result<(), LexError> {
+	lex.bump(lex.remainder().len());
+	Err(LexError::UnterminatedComment)
+}
+
+/// A broad classification of tokens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenCategory {
+	/// Brackets and separators.
+	Punctuation,
+	/// Operators, including assignment.
+	Operator,
+	/// Reserved words.
+	Keyword,
+	/// Names.
+	Identifier,
+	/// Constants.
+	Literal,
+	/// Comments.
+	Comment,
+	/// Text that could not be lexed.
+	Error,
+}
+
+impl TokenName {
+	/// Returns the category this kind of token belongs to.
+	pub fn categorize(self) -> TokenCategory {
+		use TokenName::*;
+		match self {
+			LeftParen | RightParen | LeftBrace | RightBrace | LeftBracket
+			| RightBracket | Semicolon | Comma | Dot | Colon => {
+				TokenCategory::Punctuation
+			}
+			Assign | Plus | Minus | Star | Slash | Percent | Ampersand
+			| Pipe | Bang | Equal | NotEqual | Less | LessEqual | Greater
+			| GreaterEqual | ShiftLeft | ShiftRight | Increment | Decrement
+			| Question | AssignPlus | AssignMinus | AssignStar
+			| AssignSlash | AssignPercent | AssignAmpersand | AssignPipe
+			| AssignEqual | AssignNotEqual | AssignLess | AssignLessEqual
+			| AssignGreater | AssignGreaterEqual | AssignShiftLeft
+			| AssignShiftRight | PlusAssign | MinusAssign | StarAssign
+			| SlashAssign | PercentAssign | AmpersandAssign | PipeAssign
+			| ShiftLeftAssign | ShiftRightAssign => TokenCategory::Operator,
+			Auto | Extrn | If | Else | While | Switch | Case | Goto
+			| Return | For | Do | Rec | Asm => TokenCategory::Keyword,
+			Identifier => TokenCategory::Identifier,
+			Number | OctalNumber | HexNumber | BinaryNumber | String | Char => {
+				TokenCategory::Literal
+			}
+			LineComment => TokenCategory::Comment,
+			Error => TokenCategory::Error,
+		}
+	}
+}
+
+impl fmt::Display for TokenName {
+	/// Formats the token the way it should appear in a diagnostic: quoted
+	/// source text for fixed tokens, and a description for everything else.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		use TokenName::*;
+		let text = match self {
+			LeftParen => "`(`",
+			RightParen => "`)`",
+			LeftBrace => "`{`",
+			RightBrace => "`}`",
+			LeftBracket => "`[`",
+			RightBracket => "`]`",
+			Semicolon => "`;`",
+			Comma => "`,`",
+			Dot => "`.`",
+			Colon => "`:`",
+			Assign => "`=`",
+			Plus => "`+`",
+			Minus => "`-`",
+			Star => "`*`",
+			Slash => "`/`",
+			Percent => "`%`",
+			Ampersand => "`&`",
+			Pipe => "`|`",
+			Bang => "`!`",
+			Equal => "`==`",
+			NotEqual => "`!=`",
+			Less => "`<`",
+			LessEqual => "`<=`",
+			Greater => "`>`",
+			GreaterEqual => "`>=`",
+			ShiftLeft => "`<<`",
+			ShiftRight => "`>>`",
+			Increment => "`++`",
+			Decrement => "`--`",
+			Question => "`?`",
+			AssignPlus => "`=+`",
+			AssignMinus => "`=-`",
+			AssignStar => "`=*`",
+			AssignSlash => "`=/`",
+			AssignPercent => "`=%`",
+			AssignAmpersand => "`=&`",
+			AssignPipe => "`=|`",
+			AssignEqual => "`===`",
+			AssignNotEqual => "`=!=`",
+			AssignLess => "`=<`",
+			AssignLessEqual => "`=<=`",
+			AssignGreater => "`=>`",
+			AssignGreaterEqual => "`=>=`",
+			AssignShiftLeft => "`=<<`",
+			AssignShiftRight => "`=>>`",
+			PlusAssign => "`+=`",
+			MinusAssign => "`-=`",
+			StarAssign => "`*=`",
+			SlashAssign => "`/=`",
+			PercentAssign => "`%=`",
+			AmpersandAssign => "`&=`",
+			PipeAssign => "`|=`",
+			ShiftLeftAssign => "`<<=`",
+			ShiftRightAssign => "`>>=`",
+			Auto => "`auto`",
+			Extrn => "`extrn`",
+			If => "`if`",
+			Else => "`else`",
+			While => "`while`",
+			Switch => "`switch`",
+			Case => "`case`",
+			Goto => "`goto`",
+			Return => "`return`",
+			For => "`for`",
+			Do => "`do`",
+			Rec => "`rec`",
+			Asm => "`__asm`",
+			Identifier => "a name",
+			Number | OctalNumber | HexNumber | BinaryNumber => "a number",
+			String => "a string",
+			Char => "a character constant",
+			LineComment => "a comment",
+			Error => "an invalid token",
+		};
+		f.write_str(text)
+	}
+}
+
+/// A single token.
+#[derive(Copy, Clone, Debug)]
+pub struct Token {
+	/// What kind of token this is.
+	pub name: TokenName,
+	/// Where the token appears in the source.
+	pub span: Span,
+}
+
+/// The tokens of a source file, in order.
+#[derive(Debug, Default)]
+pub struct TokenList {
+	pub tokens: Vec<Token>,
+}
+
+/// Lexes the unread portion of `ctx`'s source.
+///
+/// Text that does not form a valid token is reported in `diagnostics` and
+/// becomes an [`TokenName::Error`] token, after which lexing continues.
+pub fn lex(
+	ctx: &Context,
+	config: &CompilationConfiguration,
+	diagnostics: &mut Vec<Diagnostic>,
+) -> TokenList {
+	let mut list = TokenList::default();
+	let mut lexer =
+		TokenName::lexer_with_extras(ctx.unread(), config.escapes());
+	// The spans can't be queried until the batch is finished, so only use
+	// `lexer` for the text of a token.
+	let mut spans = ctx.start_spans();
+	// How far into `ctx.unread()` the last token ends.
+	let mut consumed = 0;
+	while let Some(result) = lexer.next() {
+		let range = lexer.span();
+		consumed = range.end;
+		let span = spans.push(range.start, range.end);
+
+		let name = match result {
+			Ok(name) => name,
+			Err(error) => {
+				let message = match error {
+					LexError::Unrecognized => {
+						format!("unrecognized token `{}`", lexer.slice())
+					}
+					LexError::UnterminatedString => {
+						"unterminated string literal".to_string()
+					}
+					LexError::UnterminatedChar => {
+						"unterminated character constant".to_string()
+					}
+					LexError::UnterminatedComment => {
+						"unterminated block comment".to_string()
+					}
+				};
+				diagnostics.push(Diagnostic::error(span, message));
+				TokenName::Error
+			}
+		};
+		let name = match name {
+			TokenName::LineComment => {
+				if config.is_strict() {
+					diagnostics
+						.push(Diagnostic::extension(span, "`//` comments"));
+				}
+				continue;
+			}
+			TokenName::PlusAssign
+			| TokenName::MinusAssign
+			| TokenName::StarAssign
+			| TokenName::SlashAssign
+			| TokenName::PercentAssign
+			| TokenName::AmpersandAssign
+			| TokenName::PipeAssign
+			| TokenName::ShiftLeftAssign
+			| TokenName::ShiftRightAssign
+				if config.is_strict() =>
+			{
+				diagnostics.push(Diagnostic::extension(
+					span,
+					"`op=` compound assignments",
+				));
+				name
+			}
+			TokenName::For if !config.has_extension(Extension::For) => {
+				TokenName::Identifier
+			}
+			TokenName::Do if !config.has_extension(Extension::DoWhile) => {
+				TokenName::Identifier
+			}
+			TokenName::Rec if !config.has_extension(Extension::Records) => {
+				TokenName::Identifier
+			}
+			TokenName::Asm if !config.has_extension(Extension::Asm) => {
+				TokenName::Identifier
+			}
+			name => name,
+		};
+		list.tokens.push(Token { name, span });
+	}
+	ctx.finish_spans(spans);
+	ctx.advance_cursor(consumed);
+	list
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Extension;
+	use crate::Standard;
+
+	/// Lexes `config`'s input, and returns every token with its text and the
+	/// messages of every diagnostic.
+	fn lex_with(
+		source: &str,
+		config: &CompilationConfiguration,
+	) -> (Vec<(TokenName, String)>, Vec<String>) {
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let mut diagnostics = Vec::new();
+		let list = lex(&ctx, config, &mut diagnostics);
+		crate::validate::check_tokens(&list, &ctx);
+		let tokens = list
+			.tokens
+			.iter()
+			.map(|token| (token.name, token.span.text(&ctx).to_string()))
+			.collect();
+		let messages = diagnostics.into_iter().map(|d| d.message).collect();
+		(tokens, messages)
+	}
+
+	fn lex_source(source: &str) -> (Vec<(TokenName, String)>, Vec<String>) {
+		lex_with(source, &CompilationConfiguration::for_test(source))
+	}
+
+	fn names(source: &str) -> Vec<TokenName> {
+		lex_source(source)
+			.0
+			.into_iter()
+			.map(|(name, _)| name)
+			.collect()
+	}
+
+	#[test]
+	fn compound_assignments() {
+		use TokenName::*;
+		assert_eq!(
+			names("=+ += =- -="),
+			[AssignPlus, PlusAssign, AssignMinus, MinusAssign]
+		);
+		assert_eq!(names("x=-1"), [Identifier, AssignMinus, Number]);
+		assert_eq!(names("x = -1"), [Identifier, Assign, Minus, Number]);
+		assert_eq!(
+			names("x=<<1 x==1"),
+			[
+				Identifier,
+				AssignShiftLeft,
+				Number,
+				Identifier,
+				Equal,
+				Number
+			]
+		);
+	}
+
+	#[test]
+	fn numbers() {
+		use TokenName::*;
+		assert_eq!(
+			names("0 10 017 09 0x1f 0b101"),
+			[
+				Number,
+				Number,
+				OctalNumber,
+				OctalNumber,
+				HexNumber,
+				BinaryNumber
+			]
+		);
+	}
+
+	#[test]
+	fn keywords_require_their_extensions() {
+		use TokenName::*;
+		let source = "for do rec __asm while";
+		assert_eq!(
+			names(source),
+			[Identifier, Identifier, Identifier, Identifier, While]
+		);
+
+		let mut config = CompilationConfiguration::for_test(source);
+		config.extensions = vec![
+			Extension::For,
+			Extension::DoWhile,
+			Extension::Records,
+			Extension::Asm,
+		];
+		let tokens: Vec<_> = lex_with(source, &config)
+			.0
+			.into_iter()
+			.map(|(name, _)| name)
+			.collect();
+		assert_eq!(tokens, [For, Do, Rec, Asm, While]);
+	}
+
+	#[test]
+	fn quoted() {
+		let (tokens, messages) = lex_source(r#"'a' "a*"b\"c" 'b'"#);
+		assert_eq!(
+			tokens,
+			[
+				(TokenName::Char, "'a'".to_string()),
+				(TokenName::String, r#""a*"b\"c""#.to_string()),
+				(TokenName::Char, "'b'".to_string()),
+			]
+		);
+		assert_eq!(messages, Vec::<String>::new());
+	}
+
+	#[test]
+	fn errors_do_not_stop_lexing() {
+		let (tokens, messages) = lex_source("a @ b \"c\nd");
+		assert_eq!(
+			tokens,
+			[
+				(TokenName::Identifier, "a".to_string()),
+				(TokenName::Error, "@".to_string()),
+				(TokenName::Identifier, "b".to_string()),
+				(TokenName::Error, "\"c".to_string()),
+				(TokenName::Identifier, "d".to_string()),
+			]
+		);
+		assert_eq!(
+			messages,
+			["unrecognized token `@`", "unterminated string literal"]
+		);
+	}
+
+	#[test]
+	fn unterminated_comment() {
+		let (tokens, messages) = lex_source("x = 1 /* oops\n;\n");
+		assert_eq!(
+			tokens,
+			[
+				(TokenName::Identifier, "x".to_string()),
+				(TokenName::Assign, "=".to_string()),
+				(TokenName::Number, "1".to_string()),
+				(TokenName::Error, "/* oops\n;\n".to_string()),
+			]
+		);
+		assert_eq!(messages, ["unterminated block comment"]);
+
+		let (tokens, _) = lex_source("a /* b */ c /* d");
+		assert_eq!(tokens.len(), 3);
+		assert_eq!(tokens[2], (TokenName::Error, "/* d".to_string()));
+	}
+
+	#[test]
+	fn comments() {
+		let source = "a /* b */ c // d\ne";
+		assert_eq!(names(source), [TokenName::Identifier; 3]);
+		assert_eq!(lex_source(source).1, Vec::<String>::new());
+
+		let mut config = CompilationConfiguration::for_test(source);
+		config.standard = Standard::B1969;
+		assert_eq!(
+			lex_with(source, &config).1,
+			["`//` comments are an extension to B"]
+		);
+	}
+
+	#[test]
+	fn strict_mode_diagnoses_op_assign() {
+		let source = "a += 1; a =+ 1;";
+		let mut config = CompilationConfiguration::for_test(source);
+		config.standard = Standard::B1969;
+		assert_eq!(
+			lex_with(source, &config).1,
+			["`op=` compound assignments are an extension to B"]
+		);
+	}
+}
@@ -1,64 +1,321 @@
-use std::fs::File;
-use std::io::{Stdin, Stdout};
-use std::path::PathBuf;
+use std::io;
+use std::io::{Read, Write};
+use std::str::FromStr;
 
 pub mod ast;
+pub mod check;
+pub mod diagnostic;
+pub mod dump;
+pub mod lex;
+pub mod parse;
+pub mod span_index;
+pub mod stats;
+pub mod stream;
+pub mod validate;
 
-pub enum ProgramSource {
-	Path(PathBuf),
-	File(File),
-	Stdin(Stdin),
+pub use diagnostic::Diagnostic;
+pub use stream::{ProgramSink, ProgramSource};
+
+/// An opt-in language extension, beyond what the B reference describes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Extension {
+	/// C-style `for (init; cond; step) body` loops.
+	For,
+	/// `do body while (cond);` loops.
+	DoWhile,
+	/// `0x` hexadecimal and `0b` binary integer literals.
+	ModernLiterals,
+	/// `rec name { a; b; }` word-offset records and `p.a` member access.
+	Records,
+	/// `__asm("...")` inline assembly statements.
+	Asm,
+}
+
+impl FromStr for Extension {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"for" => Ok(Extension::For),
+			"do-while" => Ok(Extension::DoWhile),
+			"modern-literals" => Ok(Extension::ModernLiterals),
+			"records" => Ok(Extension::Records),
+			"asm" => Ok(Extension::Asm),
+			_ => Err(format!("unknown extension `{}`", s)),
+		}
+	}
+}
+
+/// How closely the accepted language follows the B reference.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Standard {
+	/// B as described by the 1969-era reference: no `//` comments, only the
+	/// `=op` spelling of compound assignment, and only `*` escapes.
+	B1969,
+	/// The reference language plus the conveniences `badc` accepts by default.
+	#[default]
+	Extended,
+}
+
+impl FromStr for Standard {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"b1969" => Ok(Standard::B1969),
+			"extended" => Ok(Standard::Extended),
+			_ => Err(format!("unknown standard `{}`", s)),
+		}
+	}
+}
+
+impl Standard {
+	/// Returns the escape dialect used when none is given explicitly: only `*`
+	/// escapes in strict B, since a `\` is an ordinary character there.
+	pub fn default_escapes(self) -> EscapeDialect {
+		match self {
+			Standard::B1969 => EscapeDialect::Star,
+			Standard::Extended => EscapeDialect::Both,
+		}
+	}
+}
+
+/// Which escape character string and character literals use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EscapeDialect {
+	/// Historical B escapes, e.g. `*n` and `**`.
+	Star,
+	/// C-style escapes, e.g. `\n` and `\\`.
+	Backslash,
+	/// Both of the above.
+	Both,
+}
+
+impl FromStr for EscapeDialect {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"star" => Ok(EscapeDialect::Star),
+			"backslash" => Ok(EscapeDialect::Backslash),
+			"both" => Ok(EscapeDialect::Both),
+			_ => Err(format!("unknown escape dialect `{}`", s)),
+		}
+	}
+}
+
+/// How `--token-stats` writes its report.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+	/// Indented, human-readable text.
+	Text,
+	/// A single JSON object.
+	Json,
 }
 
-pub enum ProgramSink {
-	Path(PathBuf),
-	File(File),
-	Stdout(Stdout),
+impl FromStr for StatsFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(StatsFormat::Text),
+			"json" => Ok(StatsFormat::Json),
+			_ => Err(format!("unknown statistics format `{}`", s)),
+		}
+	}
 }
 
 pub struct CompilationConfiguration {
-	pub input: ProgramSource,
+	pub input: Box<dyn ProgramSource>,
+	pub standard: Standard,
+	pub extensions: Vec<Extension>,
+	/// The escape dialect, or `None` for the standard's default; see
+	/// [`Standard::default_escapes()`].
+	pub escapes: Option<EscapeDialect>,
 	pub print_tokens: bool,
 	pub print_ast: bool,
-	pub output: ProgramSink,
-	pub print_tokens_output: ProgramSink,
-	pub print_ast_output: ProgramSink,
+	/// The format to write token statistics in, if they were asked for.
+	pub token_stats: Option<StatsFormat>,
+	pub output: Box<dyn ProgramSink>,
+	pub print_tokens_output: Box<dyn ProgramSink>,
+	pub print_ast_output: Box<dyn ProgramSink>,
+	pub token_stats_output: Box<dyn ProgramSink>,
 }
 
-#[derive(Debug)]
-pub enum Token {
-	LeftParen,
-	RightParen,
-	LeftBrace,
-	RightBrace,
-	Semicolon,
-	SingleQuote,
-	Codepoint(char),
-	Identifier(String),
+impl CompilationConfiguration {
+	/// Returns whether only strict 1969 B is accepted, in which case every
+	/// extended construct should be diagnosed as an extension.
+	pub fn is_strict(&self) -> bool {
+		self.standard == Standard::B1969
+	}
+
+	/// Returns whether the given language extension has been enabled.
+	pub fn has_extension(&self, extension: Extension) -> bool {
+		self.extensions.contains(&extension)
+	}
+
+	/// Returns the escape dialect that string and character literals use.
+	pub fn escapes(&self) -> EscapeDialect {
+		self.escapes
+			.unwrap_or_else(|| self.standard.default_escapes())
+	}
 }
 
-#[derive(Debug)]
-pub struct TokenList {
-	pub tokens: Vec<Token>,
+#[cfg(test)]
+impl CompilationConfiguration {
+	/// Creates a configuration that compiles `source` with the default options,
+	/// discarding all output.
+	pub(crate) fn for_test(source: &str) -> Self {
+		let sink = || Box::new(stream::StreamSink::new(io::sink()));
+		Self {
+			input: Box::new(stream::MemorySource {
+				path: "test.b".into(),
+				text: source.to_string(),
+			}),
+			standard: Standard::default(),
+			extensions: Vec::new(),
+			escapes: None,
+			print_tokens: false,
+			print_ast: false,
+			token_stats: None,
+			output: sink(),
+			print_tokens_output: sink(),
+			print_ast_output: sink(),
+			token_stats_output: sink(),
+		}
+	}
 }
 
-#[derive(Debug)]
-pub struct SyntaxTree {}
+/// The result of compiling a single translation unit.
+pub struct CompilationResult {
+	/// The context the translation unit was parsed in, which is needed to
+	/// interpret the spans of `diagnostics`.
+	pub context: ast::Context,
+	/// Every error and warning produced, in order.
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompilationResult {
+	/// Returns whether any of the diagnostics are errors.
+	pub fn has_errors(&self) -> bool {
+		self.diagnostics
+			.iter()
+			.any(|d| d.level == diagnostic::Level::Error)
+	}
+}
+
+/// Writes a dump of `tokens` to `print_tokens_output`, if `print_tokens` is
+/// set in `config`.
+pub fn print_tokens(
+	tokens: &lex::TokenList,
+	ctx: &ast::Context,
+	config: &CompilationConfiguration,
+) -> io::Result<()> {
+	if !config.print_tokens {
+		return Ok(());
+	}
+	let mut out = config.print_tokens_output.open()?;
+	dump::dump_tokens(tokens, ctx, &mut out)?;
+	out.flush()
+}
 
-pub fn lex(_config: &CompilationConfiguration) -> TokenList {
-	let list: TokenList = TokenList { tokens: Vec::new() };
-	list
+/// Writes statistics about `tokens` to `token_stats_output`, if `token_stats`
+/// is set in `config`.
+pub fn print_token_stats(
+	tokens: &lex::TokenList,
+	ctx: &ast::Context,
+	config: &CompilationConfiguration,
+) -> io::Result<()> {
+	let Some(format) = config.token_stats else {
+		return Ok(());
+	};
+	let stats = stats::TokenStats::new(tokens, ctx);
+	let mut out = config.token_stats_output.open()?;
+	match format {
+		StatsFormat::Text => stats.write_text(&mut out)?,
+		StatsFormat::Json => stats.write_json(&mut out)?,
+	}
+	out.flush()
 }
 
-pub fn parse(
-	_token_stream: TokenList,
-	_config: &CompilationConfiguration,
-) -> SyntaxTree {
-	SyntaxTree {}
+/// Writes a tree dump of `program` to `print_ast_output`, if `print_ast` is set
+/// in `config`.
+pub fn print_ast(
+	program: &ast::Program,
+	ctx: &ast::Context,
+	config: &CompilationConfiguration,
+) -> io::Result<()> {
+	if !config.print_ast {
+		return Ok(());
+	}
+	let mut out = config.print_ast_output.open()?;
+	dump::dump_ast(program, ctx, &mut out)?;
+	out.flush()
 }
 
-pub fn compile(config: &CompilationConfiguration) -> SyntaxTree {
-	let lex: TokenList = lex(config);
-	let tree: SyntaxTree = parse(lex, config);
-	tree
+/// Reads, lexes, parses, and checks `config.input`, writing any requested dumps
+/// along the way.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if `config` asks for strict B with
+/// only backslash escapes, since strict B only has `*` escapes.
+pub fn compile(
+	config: &CompilationConfiguration,
+) -> io::Result<CompilationResult> {
+	if config.is_strict() && config.escapes() == EscapeDialect::Backslash {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"strict B only has `*` escapes, but the escape dialect is \
+			 `backslash`",
+		));
+	}
+	let mut source = String::new();
+	config.input.open()?.read_to_string(&mut source)?;
+	let ctx = ast::Context::new(config.input.path().to_path_buf(), source);
+	let mut diagnostics = Vec::new();
+
+	let tokens = lex::lex(&ctx, config, &mut diagnostics);
+	#[cfg(debug_assertions)]
+	validate::check_tokens(&tokens, &ctx);
+	print_tokens(&tokens, &ctx, config)?;
+	print_token_stats(&tokens, &ctx, config)?;
+	let program = parse::parse(tokens, &ctx, config, &mut diagnostics);
+	#[cfg(debug_assertions)]
+	validate::check_spans(&program, &ctx);
+	check::check_declarations(&program, &mut diagnostics);
+	print_ast(&program, &ctx, config)?;
+
+	Ok(CompilationResult {
+		context: ctx,
+		diagnostics,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn strict_mode_rejects_backslash_only_escapes() {
+		let mut config = CompilationConfiguration::for_test("s \"a*nb\";");
+		config.standard = Standard::B1969;
+		config.escapes = Some(EscapeDialect::Backslash);
+		let err = compile(&config).err().expect("conflicting options");
+		assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+		config.escapes = Some(EscapeDialect::Star);
+		assert!(!compile(&config).unwrap().has_errors());
+	}
+
+	#[test]
+	fn strict_mode_defaults_to_star_escapes() {
+		let mut config =
+			CompilationConfiguration::for_test("main() { s = \"a\\\"; }");
+		config.standard = Standard::B1969;
+		assert_eq!(config.escapes(), EscapeDialect::Star);
+		assert!(compile(&config).unwrap().diagnostics.is_empty());
+
+		config.standard = Standard::Extended;
+		assert_eq!(config.escapes(), EscapeDialect::Both);
+		assert!(compile(&config).unwrap().has_errors());
+	}
 }
@@ -0,0 +1,1474 @@
+//! The parser, which turns a [`TokenList`] into a [`Program`].
+//!
+//! This is a hand-written recursive-descent parser following the grammar in
+//! <https://www.bell-labs.com/usr/dmr/www/kbman.pdf> S2.1, with a Pratt parser
+//! for binary operator expressions. On a syntax error it reports a diagnostic,
+//! skips ahead to the end of the statement or definition, and keeps going,
+//! leaving an error node behind where one is representable.
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::lex::Token;
+use crate::lex::TokenList;
+use crate::lex::TokenName;
+use crate::CompilationConfiguration;
+use crate::EscapeDialect;
+use crate::Extension;
+
+/// Parses `tokens` into a program allocated on `ctx`'s arena.
+///
+/// Syntax errors are reported in `diagnostics`; the returned program contains
+/// everything that could be recovered.
+pub fn parse<'ctx>(
+	tokens: TokenList,
+	ctx: &'ctx Context,
+	config: &CompilationConfiguration,
+	diagnostics: &mut Vec<Diagnostic>,
+) -> Program<'ctx> {
+	let mut parser = Parser {
+		ctx,
+		config,
+		tokens: tokens.tokens,
+		pos: 0,
+		depth: 0,
+		last_error: None,
+		diagnostics,
+	};
+	parser.program()
+}
+
+/// How deeply statements and expressions may nest.
+///
+/// The parser, and everything that walks the tree it produces, recurses once
+/// per level; past this, a program is reported as an error rather than risking
+/// a stack overflow.
+const MAX_DEPTH: usize = 128;
+
+/// Marks that parsing failed, and that a diagnostic has already been reported.
+struct Reported;
+
+type Result<T> = std::result::Result<T, Reported>;
+
+struct Parser<'a, 'ctx> {
+	ctx: &'ctx Context,
+	config: &'a CompilationConfiguration,
+	tokens: Vec<Token>,
+	pos: usize,
+	// How many enclosing statements and expressions are being parsed. This is
+	// reset by `stmt()` when it recovers, rather than unwound on every error.
+	depth: usize,
+	// The position of the token that the last syntax error was reported on.
+	last_error: Option<usize>,
+	diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'ctx> Parser<'_, 'ctx> {
+	/// Returns the kind of the next token, if there is one.
+	fn peek(&self) -> Option<TokenName> {
+		self.tokens.get(self.pos).map(|token| token.name)
+	}
+
+	/// Returns the kind of the token after the next one, if there is one.
+	fn peek_second(&self) -> Option<TokenName> {
+		self.tokens.get(self.pos + 1).map(|token| token.name)
+	}
+
+	/// Consumes the next token.
+	///
+	/// # Panics
+	///
+	/// Panics if there are no tokens left.
+	fn bump(&mut self) -> Token {
+		let token = self.tokens[self.pos];
+		self.pos += 1;
+		token
+	}
+
+	/// Consumes the next token if it is a `name`.
+	fn eat(&mut self, name: TokenName) -> Option<Token> {
+		if self.peek() == Some(name) {
+			Some(self.bump())
+		} else {
+			None
+		}
+	}
+
+	/// Consumes the next token, which must be a `name`.
+	fn expect(&mut self, name: TokenName) -> Result<Token> {
+		match self.eat(name) {
+			Some(token) => Ok(token),
+			None => Err(self.error_expected(&name.to_string())),
+		}
+	}
+
+	/// Reports that `what` was expected at the next token.
+	///
+	/// Only the first such error at each token is reported, so that an error
+	/// expression which consumed nothing does not cascade into a second error
+	/// at the same place.
+	fn error_expected(&mut self, what: &str) -> Reported {
+		if self.last_error.replace(self.pos) == Some(self.pos) {
+			return Reported;
+		}
+		let (span, found) = match self.tokens.get(self.pos) {
+			// The lexer has already reported this.
+			Some(token) if token.name == TokenName::Error => return Reported,
+			Some(token) => (token.span, token.name.to_string()),
+			None => {
+				let last = self.tokens.last().expect("no tokens to report on");
+				// An unterminated comment swallowed whatever was missing, and
+				// the lexer has already reported it.
+				if last.name == TokenName::Error
+					&& last.span.text(self.ctx).starts_with("/*")
+				{
+					return Reported;
+				}
+				(last.span, "end of file".to_string())
+			}
+		};
+		self.diagnostics.push(Diagnostic::error(
+			span,
+			format!("expected {}, found {}", what, found),
+		));
+		Reported
+	}
+
+	/// Enters one more level of nesting, reporting an error if that is deeper
+	/// than [`MAX_DEPTH`].
+	fn enter(&mut self) -> Result<()> {
+		if self.depth < MAX_DEPTH {
+			self.depth += 1;
+			return Ok(());
+		}
+		if self.last_error.replace(self.pos) != Some(self.pos) {
+			let span = match self.tokens.get(self.pos) {
+				Some(token) => token.span,
+				None => {
+					self.tokens.last().expect("no tokens to report on").span
+				}
+			};
+			self.diagnostics.push(Diagnostic::error(
+				span,
+				"statements and expressions are nested too deeply",
+			));
+		}
+		Err(Reported)
+	}
+
+	/// Warns that an extension is in use, if only strict B is permitted.
+	fn extension(&mut self, span: Span, what: &str) {
+		if self.config.is_strict() {
+			self.diagnostics.push(Diagnostic::extension(span, what));
+		}
+	}
+
+	/// Returns a span covering every token from the one at `start` up to the
+	/// most recently consumed one.
+	///
+	/// If no tokens have been consumed since `start`, this is an empty span
+	/// just past the previous token, so that it stays within whatever node
+	/// encloses it.
+	fn span_from(&self, start: usize) -> Span {
+		if self.pos <= start {
+			let offset = match start.checked_sub(1) {
+				Some(prev) => self.tokens[prev].span.range(self.ctx).1,
+				None => 0,
+			};
+			return self.ctx.empty_span(offset);
+		}
+		self.ctx
+			.join(self.tokens[start].span, self.tokens[self.pos - 1].span)
+	}
+
+	/// Skips to the end of the statement that failed to parse: just past the
+	/// next `;` or the next whole `{ ... }` block, or just before a `}` that
+	/// closes the enclosing block.
+	fn recover_stmt(&mut self) {
+		let mut depth = 0usize;
+		while let Some(name) = self.peek() {
+			match name {
+				TokenName::Semicolon if depth == 0 => {
+					self.bump();
+					return;
+				}
+				TokenName::RightBrace if depth == 0 => return,
+				TokenName::RightBrace if depth == 1 => {
+					self.bump();
+					return;
+				}
+				TokenName::RightBrace => depth -= 1,
+				TokenName::LeftBrace => depth += 1,
+				_ => {}
+			}
+			self.bump();
+		}
+	}
+
+	/// Skips to the end of the definition that failed to parse: just past the
+	/// next `;` or `}` at the top level.
+	fn recover_def(&mut self) {
+		let mut depth = 0usize;
+		while let Some(name) = self.peek() {
+			self.bump();
+			match name {
+				TokenName::LeftBrace => depth += 1,
+				TokenName::RightBrace if depth <= 1 => return,
+				TokenName::RightBrace => depth -= 1,
+				TokenName::Semicolon if depth == 0 => return,
+				_ => {}
+			}
+		}
+	}
+
+	fn alloc<T>(&self, value: T) -> &'ctx T {
+		self.ctx.arena.alloc(value)
+	}
+
+	fn alloc_slice<T>(&self, values: Vec<T>) -> &'ctx [T] {
+		self.ctx.arena.alloc_slice_fill_iter(values)
+	}
+
+	/// Parses a name.
+	fn id(&mut self) -> Result<Id<'ctx>> {
+		let token = self.expect(TokenName::Identifier)?;
+		Ok(Id {
+			name: token.span.text(self.ctx),
+			span: token.span,
+		})
+	}
+
+	/// Parses a whole program.
+	///
+	/// Corresponds to `program` in the B grammar.
+	fn program(&mut self) -> Program<'ctx> {
+		let mut defs = Vec::new();
+		while self.peek().is_some() {
+			match self.def() {
+				Ok(def) => defs.push(def),
+				Err(Reported) => self.recover_def(),
+			}
+		}
+		Program {
+			defs: self.alloc_slice(defs),
+		}
+	}
+
+	/// Parses a definition.
+	///
+	/// Corresponds to `definition` in the B grammar.
+	fn def(&mut self) -> Result<Def<'ctx>> {
+		let start = self.pos;
+		if let Some(rec) = self.eat(TokenName::Rec) {
+			self.extension(rec.span, "records");
+			let name = self.id()?;
+			self.expect(TokenName::LeftBrace)?;
+			let mut members = Vec::new();
+			while self.eat(TokenName::RightBrace).is_none() {
+				members.push(self.id()?);
+				self.expect(TokenName::Semicolon)?;
+			}
+			return Ok(Def::Record(Record {
+				name,
+				members: self.alloc_slice(members),
+				span: self.span_from(start),
+			}));
+		}
+
+		let name = self.id()?;
+		if self.eat(TokenName::LeftParen).is_some() {
+			let mut params = Vec::new();
+			if self.eat(TokenName::RightParen).is_none() {
+				loop {
+					params.push(self.id()?);
+					if self.eat(TokenName::Comma).is_none() {
+						break;
+					}
+				}
+				self.expect(TokenName::RightParen)?;
+			}
+
+			let body = self.stmt();
+			let body = match body.kind {
+				StmtKind::Block(stmts) => stmts,
+				_ => self.alloc_slice(vec![body]),
+			};
+			return Ok(Def::Func(Func {
+				name,
+				params: self.alloc_slice(params),
+				body,
+				span: self.span_from(start),
+			}));
+		}
+
+		let size_start = self.pos;
+		let size = match self.eat(TokenName::LeftBracket) {
+			Some(_) => {
+				let size = match self.peek() {
+					Some(TokenName::RightBracket) => ArraySize::Implicit,
+					_ => ArraySize::Explicit(self.constant()?),
+				};
+				self.expect(TokenName::RightBracket)?;
+				Some((size, self.span_from(size_start)))
+			}
+			None => None,
+		};
+
+		let mut inits = Vec::new();
+		if self.eat(TokenName::Semicolon).is_none() {
+			loop {
+				inits.push(self.init_val()?);
+				if self.eat(TokenName::Comma).is_none() {
+					break;
+				}
+			}
+			self.expect(TokenName::Semicolon)?;
+		}
+		Ok(Def::Global(Global {
+			name,
+			size,
+			inits: self.alloc_slice(inits),
+			span: self.span_from(start),
+		}))
+	}
+
+	/// Parses an initializer.
+	///
+	/// Corresponds to `ival` in the B grammar.
+	fn init_val(&mut self) -> Result<InitVal<'ctx>> {
+		match self.peek() {
+			Some(TokenName::Identifier) => Ok(InitVal::Id(self.id()?)),
+			_ if self.at_constant() => Ok(InitVal::Const(self.constant()?)),
+			_ => Err(self.error_expected("a name or constant")),
+		}
+	}
+
+	/// Returns whether the next token begins a constant.
+	fn at_constant(&self) -> bool {
+		matches!(
+			self.peek(),
+			Some(
+				TokenName::Number
+					| TokenName::OctalNumber
+					| TokenName::HexNumber
+					| TokenName::BinaryNumber
+					| TokenName::String
+					| TokenName::Char
+			)
+		)
+	}
+
+	/// Parses a constant.
+	///
+	/// Corresponds to `constant` in the B grammar.
+	fn constant(&mut self) -> Result<Const<'ctx>> {
+		match self.peek() {
+			Some(TokenName::String) => Ok(Const::Str(self.string()?)),
+			Some(TokenName::Char) => {
+				let token = self.bump();
+				let value = self.unescape(token);
+				if value.is_empty() {
+					self.diagnostics.push(Diagnostic::error(
+						token.span,
+						"empty character constant",
+					));
+					return Err(Reported);
+				}
+				Ok(Const::Char(Char {
+					value,
+					span: token.span,
+				}))
+			}
+			_ => self.int(),
+		}
+	}
+
+	/// Parses a string literal.
+	fn string(&mut self) -> Result<Str<'ctx>> {
+		let token = self.expect(TokenName::String)?;
+		Ok(Str {
+			value: self.unescape(token),
+			span: token.span,
+		})
+	}
+
+	/// Returns the value of a string or character literal: its text without
+	/// its quotes, with escape sequences decoded.
+	///
+	/// Unknown escape sequences are reported, and decode to the escaped
+	/// character itself.
+	fn unescape(&mut self, token: Token) -> &'ctx str {
+		let text = self.quoted_text(token);
+		let dialect = self.config.escapes();
+		let mut value = String::with_capacity(text.len());
+		let mut chars = text.char_indices();
+		while let Some((i, c)) = chars.next() {
+			let decode = match c {
+				'*' if dialect != EscapeDialect::Backslash => star_escape,
+				'\\' if dialect != EscapeDialect::Star => backslash_escape,
+				_ => {
+					value.push(c);
+					continue;
+				}
+			};
+			// The lexer never ends a literal on an escape character.
+			let Some((j, escaped)) = chars.next() else {
+				value.push(c);
+				break;
+			};
+			// Offset by one for the opening quote.
+			let span =
+				self.ctx
+					.subspan(token.span, 1 + i, 1 + j + escaped.len_utf8());
+			if c == '\\' {
+				self.extension(span, "`\\` escapes");
+			}
+			match decode(escaped) {
+				Some(decoded) => value.push(decoded),
+				None => {
+					self.diagnostics.push(Diagnostic::error(
+						span,
+						format!("unknown escape sequence `{}{}`", c, escaped),
+					));
+					value.push(escaped);
+				}
+			}
+		}
+		if value == text {
+			return text;
+		}
+		self.ctx.arena.alloc_str(&value)
+	}
+
+	/// Returns the text of a string or character literal, without its quotes.
+	fn quoted_text(&self, token: Token) -> &'ctx str {
+		let text = token.span.text(self.ctx);
+		&text[1..text.len() - 1]
+	}
+
+	/// Parses an integer constant.
+	///
+	/// Following the B reference, a leading `0` makes the constant octal. The
+	/// digits `8` and `9` are still allowed in it, and keep their usual value,
+	/// so that `09` is the same as `011`.
+	fn int(&mut self) -> Result<Const<'ctx>> {
+		let (radix, prefix, modern) = match self.peek() {
+			Some(TokenName::Number) => (10, 0, None),
+			Some(TokenName::OctalNumber) => (8, 1, None),
+			Some(TokenName::HexNumber) => (16, 2, Some("hexadecimal literals")),
+			Some(TokenName::BinaryNumber) => (2, 2, Some("binary literals")),
+			_ => return Err(self.error_expected("a constant")),
+		};
+		let token = self.bump();
+		if let Some(what) = modern {
+			if self.config.has_extension(Extension::ModernLiterals) {
+				self.extension(token.span, what);
+			} else {
+				self.diagnostics.push(Diagnostic::error(
+					token.span,
+					format!("{} require `--ext=modern-literals`", what),
+				));
+			}
+		}
+
+		// The lexer has already checked the digits, so this only needs to
+		// watch for overflow.
+		let digits = &token.span.text(self.ctx)[prefix..];
+		let value = digits.chars().try_fold(0u128, |value, c| {
+			let digit = c.to_digit(16).expect("digit checked by the lexer");
+			value.checked_mul(radix)?.checked_add(digit.into())
+		});
+		match value {
+			Some(value) => Ok(Const::Int(Int {
+				value,
+				span: token.span,
+			})),
+			None => {
+				self.diagnostics.push(Diagnostic::error(
+					token.span,
+					"integer constant is too large",
+				));
+				Err(Reported)
+			}
+		}
+	}
+
+	/// Parses a statement, recovering from any syntax error in it.
+	///
+	/// Corresponds to `statement` in the B grammar.
+	fn stmt(&mut self) -> Stmt<'ctx> {
+		let start = self.pos;
+		let depth = self.depth;
+		let kind = match self.enter().and_then(|()| self.stmt_kind()) {
+			Ok(kind) => kind,
+			Err(Reported) => {
+				self.recover_stmt();
+				StmtKind::Error
+			}
+		};
+		self.depth = depth;
+		Stmt {
+			kind,
+			span: self.span_from(start),
+		}
+	}
+
+	fn stmt_kind(&mut self) -> Result<StmtKind<'ctx>> {
+		let Some(name) = self.peek() else {
+			return Err(self.error_expected("a statement"));
+		};
+		let kind = match name {
+			TokenName::Auto => {
+				self.bump();
+				let mut decls = Vec::new();
+				loop {
+					let id = self.id()?;
+					let init = if self.at_constant() {
+						Some(self.constant()?)
+					} else {
+						None
+					};
+					decls.push((id, init));
+					if self.eat(TokenName::Comma).is_none() {
+						break;
+					}
+				}
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Auto {
+					decls: self.alloc_slice(decls),
+				}
+			}
+			TokenName::Extrn => {
+				self.bump();
+				let mut decls = Vec::new();
+				loop {
+					decls.push(self.id()?);
+					if self.eat(TokenName::Comma).is_none() {
+						break;
+					}
+				}
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Extrn {
+					decls: self.alloc_slice(decls),
+				}
+			}
+			TokenName::LeftBrace => {
+				self.bump();
+				let mut stmts = Vec::new();
+				while !matches!(self.peek(), Some(TokenName::RightBrace) | None)
+				{
+					stmts.push(self.stmt());
+				}
+				self.expect(TokenName::RightBrace)?;
+				StmtKind::Block(self.alloc_slice(stmts))
+			}
+			TokenName::If => {
+				self.bump();
+				let cond = self.paren_expr()?;
+				let body = self.stmt();
+				let elze = match self.eat(TokenName::Else) {
+					Some(_) => {
+						let elze = self.stmt();
+						Some(self.alloc(elze))
+					}
+					None => None,
+				};
+				StmtKind::If {
+					cond,
+					body: self.alloc(body),
+					elze,
+				}
+			}
+			TokenName::While => {
+				self.bump();
+				let cond = self.paren_expr()?;
+				let body = self.stmt();
+				StmtKind::While {
+					cond,
+					body: self.alloc(body),
+				}
+			}
+			TokenName::Do => {
+				let token = self.bump();
+				self.extension(token.span, "`do`-`while` loops");
+				let body = self.stmt();
+				self.expect(TokenName::While)?;
+				let cond = self.paren_expr()?;
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::DoWhile {
+					body: self.alloc(body),
+					cond,
+				}
+			}
+			TokenName::For => {
+				let token = self.bump();
+				self.extension(token.span, "`for` loops");
+				self.expect(TokenName::LeftParen)?;
+				let init = self.expr_until(TokenName::Semicolon)?;
+				self.expect(TokenName::Semicolon)?;
+				let cond = self.expr_until(TokenName::Semicolon)?;
+				self.expect(TokenName::Semicolon)?;
+				let step = self.expr_until(TokenName::RightParen)?;
+				self.expect(TokenName::RightParen)?;
+				let body = self.stmt();
+				StmtKind::For {
+					init,
+					cond,
+					step,
+					body: self.alloc(body),
+				}
+			}
+			TokenName::Switch => {
+				self.bump();
+				let switchee = self.expr()?;
+				let body = self.stmt();
+				StmtKind::Switch {
+					switchee,
+					body: self.alloc(body),
+				}
+			}
+			TokenName::Case => {
+				self.bump();
+				let value = self.constant()?;
+				self.expect(TokenName::Colon)?;
+				StmtKind::Case(value)
+			}
+			TokenName::Identifier
+				if self.peek_second() == Some(TokenName::Colon) =>
+			{
+				let label = self.id()?;
+				self.bump();
+				StmtKind::Label(label)
+			}
+			TokenName::Goto => {
+				self.bump();
+				let target = self.expr()?;
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Goto(target)
+			}
+			TokenName::Return => {
+				self.bump();
+				let value = self.expr_until(TokenName::Semicolon)?;
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Return(value)
+			}
+			TokenName::Asm => {
+				let token = self.bump();
+				self.extension(token.span, "`__asm` statements");
+				self.expect(TokenName::LeftParen)?;
+				let code = self.string()?;
+				let mut operands = Vec::new();
+				while self.eat(TokenName::Comma).is_some() {
+					operands.push(self.id()?);
+				}
+				self.expect(TokenName::RightParen)?;
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Asm {
+					code,
+					operands: self.alloc_slice(operands),
+				}
+			}
+			TokenName::Semicolon => {
+				self.bump();
+				StmtKind::Empty
+			}
+			_ => {
+				let expr = self.expr()?;
+				self.expect(TokenName::Semicolon)?;
+				StmtKind::Expr(expr)
+			}
+		};
+		Ok(kind)
+	}
+
+	/// Parses a parenthesized condition, e.g. of an `if`; the parentheses are
+	/// part of the statement's syntax, not the expression.
+	fn paren_expr(&mut self) -> Result<Expr<'ctx>> {
+		self.expect(TokenName::LeftParen)?;
+		let expr = self.expr()?;
+		self.expect(TokenName::RightParen)?;
+		Ok(expr)
+	}
+
+	/// Parses an expression, unless the next token is `end`.
+	fn expr_until(&mut self, end: TokenName) -> Result<Option<Expr<'ctx>>> {
+		if self.peek() == Some(end) {
+			return Ok(None);
+		}
+		self.expr().map(Some)
+	}
+
+	/// Parses an expression.
+	///
+	/// Corresponds to `rvalue` in the B grammar.
+	fn expr(&mut self) -> Result<Expr<'ctx>> {
+		self.expr_bp(0)
+	}
+
+	/// Parses an expression containing only infix operators whose left binding
+	/// power is at least `min_bp`; see [`infix_op`].
+	fn expr_bp(&mut self, min_bp: u8) -> Result<Expr<'ctx>> {
+		let start = self.pos;
+		let depth = self.depth;
+		self.enter()?;
+		let mut lhs = self.unary()?;
+		while let Some((op, left_bp, right_bp)) = self.peek().and_then(infix_op)
+		{
+			if left_bp < min_bp {
+				break;
+			}
+			// `lhs` becomes an operand, one level further down the tree.
+			self.enter()?;
+			self.bump();
+			let kind = match op {
+				Infix::Assign => {
+					let rhs = self.expr_bp(right_bp)?;
+					ExprKind::Assign {
+						lhs: self.alloc(lhs),
+						rhs: self.alloc(rhs),
+					}
+				}
+				Infix::CompoundAssign(kind) => {
+					let rhs = self.expr_bp(right_bp)?;
+					ExprKind::Binary {
+						lhs: self.alloc(lhs),
+						rhs: self.alloc(rhs),
+						kind,
+						is_assign: true,
+					}
+				}
+				Infix::Ternary => {
+					let yes = self.expr()?;
+					self.expect(TokenName::Colon)?;
+					let no = self.expr_bp(right_bp)?;
+					ExprKind::Ternary {
+						cond: self.alloc(lhs),
+						yes: self.alloc(yes),
+						no: self.alloc(no),
+					}
+				}
+				Infix::Binary(kind) => {
+					let rhs = self.expr_bp(right_bp)?;
+					ExprKind::Binary {
+						lhs: self.alloc(lhs),
+						rhs: self.alloc(rhs),
+						kind,
+						is_assign: false,
+					}
+				}
+			};
+			lhs = Expr {
+				kind,
+				span: self.span_from(start),
+			};
+		}
+		self.depth = depth;
+		Ok(lhs)
+	}
+
+	/// Parses a prefix unary expression.
+	fn unary(&mut self) -> Result<Expr<'ctx>> {
+		let start = self.pos;
+		let op = match self.peek() {
+			Some(TokenName::Star) => None,
+			Some(TokenName::Ampersand) => Some(UnaryOp::AddrOf),
+			Some(TokenName::Minus) => Some(UnaryOp::Minus),
+			Some(TokenName::Bang) => Some(UnaryOp::Not),
+			Some(TokenName::Increment) => Some(UnaryOp::PreInc),
+			Some(TokenName::Decrement) => Some(UnaryOp::PreDec),
+			_ => return self.postfix(),
+		};
+		let depth = self.depth;
+		self.enter()?;
+		self.bump();
+		let expr = self.unary()?;
+		self.depth = depth;
+		let expr = self.alloc(expr);
+		let kind = match op {
+			Some(kind) => ExprKind::Unary { expr, kind },
+			None => ExprKind::Deref { ptr: expr },
+		};
+		Ok(Expr {
+			kind,
+			span: self.span_from(start),
+		})
+	}
+
+	/// Parses a primary expression followed by any number of calls, indexing
+	/// operations, and postfix operators.
+	fn postfix(&mut self) -> Result<Expr<'ctx>> {
+		let start = self.pos;
+		let depth = self.depth;
+		let mut expr = self.primary()?;
+		// Each operator makes `expr` an operand, one level further down the
+		// tree.
+		loop {
+			let kind = match self.peek() {
+				Some(TokenName::LeftParen) => {
+					self.enter()?;
+					self.bump();
+					let mut args = Vec::new();
+					if self.eat(TokenName::RightParen).is_none() {
+						loop {
+							let arg = self.expr()?;
+							args.push(self.alloc(arg));
+							if self.eat(TokenName::Comma).is_none() {
+								break;
+							}
+						}
+						self.expect(TokenName::RightParen)?;
+					}
+					ExprKind::Call {
+						func: self.alloc(expr),
+						args: self.alloc_slice(args),
+					}
+				}
+				Some(TokenName::LeftBracket) => {
+					self.enter()?;
+					self.bump();
+					let index = self.expr()?;
+					self.expect(TokenName::RightBracket)?;
+					ExprKind::Index {
+						ptr: self.alloc(expr),
+						index: self.alloc(index),
+					}
+				}
+				Some(TokenName::Increment) => {
+					self.enter()?;
+					self.bump();
+					ExprKind::Unary {
+						expr: self.alloc(expr),
+						kind: UnaryOp::PostInc,
+					}
+				}
+				Some(TokenName::Decrement) => {
+					self.enter()?;
+					self.bump();
+					ExprKind::Unary {
+						expr: self.alloc(expr),
+						kind: UnaryOp::PostDec,
+					}
+				}
+				Some(TokenName::Dot)
+					if self.config.has_extension(Extension::Records) =>
+				{
+					self.enter()?;
+					let dot = self.bump();
+					self.extension(dot.span, "record member accesses");
+					ExprKind::Member {
+						ptr: self.alloc(expr),
+						member: self.id()?,
+					}
+				}
+				_ => {
+					self.depth = depth;
+					return Ok(expr);
+				}
+			};
+			expr = Expr {
+				kind,
+				span: self.span_from(start),
+			};
+		}
+	}
+
+	/// Parses a name, constant, or parenthesized expression.
+	///
+	/// If there is no valid expression here, this reports it and returns an
+	/// [`ExprKind::Error`] in its place, so that the enclosing expression is
+	/// kept.
+	fn primary(&mut self) -> Result<Expr<'ctx>> {
+		let start = self.pos;
+		let kind = match self.peek() {
+			Some(TokenName::Identifier) => {
+				ExprKind::InitVal(InitVal::Id(self.id()?))
+			}
+			_ if self.at_constant() => match self.constant() {
+				Ok(constant) => ExprKind::InitVal(InitVal::Const(constant)),
+				Err(Reported) => ExprKind::Error,
+			},
+			Some(TokenName::LeftParen) => {
+				self.bump();
+				let inner = self.expr()?;
+				self.expect(TokenName::RightParen)?;
+				ExprKind::Parens(self.alloc(inner))
+			}
+			_ => {
+				let Reported = self.error_expected("an expression");
+				// Cover text the lexer rejected, but leave anything else for
+				// the enclosing statement to recover from.
+				self.eat(TokenName::Error);
+				ExprKind::Error
+			}
+		};
+		Ok(Expr {
+			kind,
+			span: self.span_from(start),
+		})
+	}
+}
+
+/// An operator that appears between its operands.
+#[derive(Copy, Clone)]
+enum Infix {
+	/// `=`.
+	Assign,
+	/// A compound assignment, e.g. `=+` or `+=`.
+	CompoundAssign(BinaryOp),
+	/// `?`, which is followed by the rest of a `cond ? yes : no` expression.
+	Ternary,
+	/// Any other binary operator.
+	Binary(BinaryOp),
+}
+
+/// Returns the infix operator spelled by `name`, along with its left and right
+/// binding powers, for [`Parser::expr_bp`].
+///
+/// Operators with higher binding powers bind more tightly. Left-associative
+/// operators have a right binding power one higher than their left, and
+/// right-associative ones have equal binding powers.
+///
+/// From loosest- to tightest-binding, these follow the table in kbman S4: all
+/// forms of assignment and `?:` (both right-associative), then `|`, `&`,
+/// equality, relational, shift, additive, and multiplicative (all
+/// left-associative). Note that B has no separate logical `&&` and `||`; `&`
+/// and `|` fill both roles.
+fn infix_op(name: TokenName) -> Option<(Infix, u8, u8)> {
+	use TokenName::*;
+	if let Some(kind) = compound_assign_op(name) {
+		return Some((Infix::CompoundAssign(kind), 2, 2));
+	}
+	let (kind, bp) = match name {
+		Assign => return Some((Infix::Assign, 2, 2)),
+		Question => return Some((Infix::Ternary, 3, 3)),
+		Pipe => (BinaryOp::Or, 4),
+		Ampersand => (BinaryOp::And, 6),
+		Equal => (BinaryOp::Eq, 8),
+		NotEqual => (BinaryOp::Ne, 8),
+		Less => (BinaryOp::Lt, 10),
+		LessEqual => (BinaryOp::Le, 10),
+		Greater => (BinaryOp::Gt, 10),
+		GreaterEqual => (BinaryOp::Ge, 10),
+		ShiftLeft => (BinaryOp::Shl, 12),
+		ShiftRight => (BinaryOp::Shr, 12),
+		Plus => (BinaryOp::Add, 14),
+		Minus => (BinaryOp::Sub, 14),
+		Star => (BinaryOp::Mul, 16),
+		Slash => (BinaryOp::Div, 16),
+		Percent => (BinaryOp::Rem, 16),
+		_ => return None,
+	};
+	Some((Infix::Binary(kind), bp, bp + 1))
+}
+
+/// Returns the operation performed by a compound assignment operator, in either
+/// its B (`=+`) or C (`+=`) spelling.
+fn compound_assign_op(name: TokenName) -> Option<BinaryOp> {
+	use TokenName::*;
+	let kind = match name {
+		AssignPlus | PlusAssign => BinaryOp::Add,
+		AssignMinus | MinusAssign => BinaryOp::Sub,
+		AssignStar | StarAssign => BinaryOp::Mul,
+		AssignSlash | SlashAssign => BinaryOp::Div,
+		AssignPercent | PercentAssign => BinaryOp::Rem,
+		AssignAmpersand | AmpersandAssign => BinaryOp::And,
+		AssignPipe | PipeAssign => BinaryOp::Or,
+		AssignEqual => BinaryOp::Eq,
+		AssignNotEqual => BinaryOp::Ne,
+		AssignLess => BinaryOp::Lt,
+		AssignLessEqual => BinaryOp::Le,
+		AssignGreater => BinaryOp::Gt,
+		AssignGreaterEqual => BinaryOp::Ge,
+		AssignShiftLeft | ShiftLeftAssign => BinaryOp::Shl,
+		AssignShiftRight | ShiftRightAssign => BinaryOp::Shr,
+		_ => return None,
+	};
+	Some(kind)
+}
+
+/// Decodes the character after a `*` in a B escape sequence, e.g. the `n` in
+/// `*n`, per the table in the B reference.
+fn star_escape(c: char) -> Option<char> {
+	let decoded = match c {
+		'0' => '\0',
+		'e' => '\x04',
+		'(' => '{',
+		')' => '}',
+		't' => '\t',
+		'n' => '\n',
+		'*' | '\'' | '"' => c,
+		_ => return None,
+	};
+	Some(decoded)
+}
+
+/// Decodes the character after a `\` in a C-style escape sequence, e.g. the
+/// `n` in `\n`.
+fn backslash_escape(c: char) -> Option<char> {
+	let decoded = match c {
+		'0' => '\0',
+		't' => '\t',
+		'n' => '\n',
+		'r' => '\r',
+		'\\' | '\'' | '"' => c,
+		_ => return None,
+	};
+	Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Read;
+
+	use crate::ast::Context;
+	use crate::dump;
+	use crate::lex;
+	use crate::CompilationConfiguration;
+	use crate::EscapeDialect;
+	use crate::Extension;
+	use crate::Standard;
+
+	/// Lexes and parses `config`'s input, checks its spans, and returns its AST
+	/// dump along with the messages of every diagnostic.
+	fn parse(config: &CompilationConfiguration) -> (String, Vec<String>) {
+		let mut source = String::new();
+		config
+			.input
+			.open()
+			.unwrap()
+			.read_to_string(&mut source)
+			.unwrap();
+		let ctx = Context::new(config.input.path().to_path_buf(), source);
+		let mut diagnostics = Vec::new();
+		let tokens = lex::lex(&ctx, config, &mut diagnostics);
+		let program = super::parse(tokens, &ctx, config, &mut diagnostics);
+		crate::validate::check_spans(&program, &ctx);
+
+		let mut out = Vec::new();
+		dump::dump_ast(&program, &ctx, &mut out).unwrap();
+		let messages = diagnostics.into_iter().map(|d| d.message).collect();
+		(String::from_utf8(out).unwrap(), messages)
+	}
+
+	fn parse_source(source: &str) -> (String, Vec<String>) {
+		parse(&CompilationConfiguration::for_test(source))
+	}
+
+	/// Parses `stmts` as the body of a function, and returns the dump of the
+	/// body without coordinates or the enclosing function, and the messages of
+	/// every diagnostic.
+	fn parse_body(stmts: &str) -> (String, Vec<String>) {
+		let (ast, messages) = parse_source(&format!("f() {{ {} }}", stmts));
+		let body = ast
+			.lines()
+			.skip(2)
+			.map(|line| {
+				let line = line.strip_prefix("\t\t").unwrap();
+				let end = line.rfind(" [").unwrap();
+				format!("{}\n", &line[..end])
+			})
+			.collect();
+		(body, messages)
+	}
+
+	#[test]
+	fn binary_precedence() {
+		let (body, _) = parse_body("a = b | c & d == e < f << g + h * -i++;");
+		assert_eq!(
+			body,
+			"\
+Expr
+	Assign
+		Id a
+		Binary Or
+			Id b
+			Binary And
+				Id c
+				Binary Eq
+					Id d
+					Binary Lt
+						Id e
+						Binary Shl
+							Id f
+							Binary Add
+								Id g
+								Binary Mul
+									Id h
+									Unary Minus
+										Unary PostInc
+											Id i
+"
+		);
+	}
+
+	#[test]
+	fn associativity() {
+		let (body, _) = parse_body("a - b - c; a = b =+ c; a ? b : c ? d : e;");
+		assert_eq!(
+			body,
+			"\
+Expr
+	Binary Sub
+		Binary Sub
+			Id a
+			Id b
+		Id c
+Expr
+	Assign
+		Id a
+		Binary Add assign
+			Id b
+			Id c
+Expr
+	Ternary
+		Id a
+		Id b
+		Ternary
+			Id c
+			Id d
+			Id e
+"
+		);
+	}
+
+	#[test]
+	fn postfix_and_compound_assignment() {
+		let (body, messages) = parse_body("v[i](x) =<< 1; x=-1; x -= 1;");
+		assert_eq!(
+			body,
+			"\
+Expr
+	Binary Shl assign
+		Call
+			Index
+				Id v
+				Id i
+			Id x
+		Int 1
+Expr
+	Binary Sub assign
+		Id x
+		Int 1
+Expr
+	Binary Sub assign
+		Id x
+		Int 1
+"
+		);
+		assert_eq!(messages, Vec::<String>::new());
+	}
+
+	#[test]
+	fn statements() {
+		let (body, _) =
+			parse_body("l: if (a) b; else c; switch x { case 'q': goto l; }");
+		assert_eq!(
+			body,
+			"\
+Label l
+If
+	Id a
+	Expr
+		Id b
+	Expr
+		Id c
+Switch
+	Id x
+	Block
+		Case
+			Char \"q\"
+		Goto
+			Id l
+"
+		);
+	}
+
+	#[test]
+	fn for_loop_parts() {
+		let mut config = CompilationConfiguration::for_test(
+			"f() { for (a;;) x; for (;a;) x; for (;;a) x; for (a; b; c) ; }",
+		);
+		config.extensions = vec![Extension::For];
+		let (ast, messages) = parse(&config);
+		assert_eq!(messages, Vec::<String>::new());
+		assert_eq!(
+			ast,
+			"\
+Program test.b
+	Func f [1:1]
+		For [1:7]
+			Init [1:12]
+				Id a [1:12]
+			Expr [1:17]
+				Id x [1:17]
+		For [1:20]
+			Cond [1:26]
+				Id a [1:26]
+			Expr [1:30]
+				Id x [1:30]
+		For [1:33]
+			Step [1:40]
+				Id a [1:40]
+			Expr [1:43]
+				Id x [1:43]
+		For [1:46]
+			Init [1:51]
+				Id a [1:51]
+			Cond [1:54]
+				Id b [1:54]
+			Step [1:57]
+				Id c [1:57]
+			Empty [1:60]
+"
+		);
+	}
+
+	#[test]
+	fn recovery_continues_with_the_next_statement() {
+		let (body, messages) =
+			parse_body("a = (b; return c; if d; e; while f { g; } h;");
+		assert_eq!(
+			body,
+			"\
+Error
+Return
+	Id c
+Error
+Expr
+	Id e
+Error
+Expr
+	Id h
+"
+		);
+		assert_eq!(
+			messages,
+			[
+				"expected `)`, found `;`",
+				"expected `(`, found a name",
+				"expected `(`, found a name",
+			]
+		);
+	}
+
+	#[test]
+	fn recovery_continues_with_the_next_definition() {
+		let (ast, messages) = parse_source("a 1 b; f() { x; } g(;\nh() {}");
+		let defs: Vec<_> = ast
+			.lines()
+			.filter(|line| line.starts_with("\tFunc"))
+			.collect();
+		assert_eq!(defs, ["\tFunc f [1:8]", "\tFunc h [2:1]"]);
+		assert_eq!(
+			messages,
+			["expected `;`, found a name", "expected a name, found `;`"]
+		);
+	}
+
+	#[test]
+	fn escapes() {
+		let (body, messages) =
+			parse_body(r#"s = "*(*t*n*0*e**\"\n"; c = '*'';"#);
+		assert_eq!(
+			body,
+			r#"Expr
+	Assign
+		Id s
+		Str "{\t\n\0\u{4}*\"\n"
+Expr
+	Assign
+		Id c
+		Char "'"
+"#
+		);
+		assert_eq!(messages, Vec::<String>::new());
+
+		let (_, messages) = parse_body(r#"s = "*q\q";"#);
+		assert_eq!(
+			messages,
+			[
+				"unknown escape sequence `*q`",
+				"unknown escape sequence `\\q`"
+			]
+		);
+	}
+
+	#[test]
+	fn escape_dialects() {
+		let source = r#"s "*n\n";"#;
+		let mut config = CompilationConfiguration::for_test(source);
+		config.escapes = Some(EscapeDialect::Star);
+		assert!(parse(&config).0.contains(r#"Str "\n\\n""#));
+		config.escapes = Some(EscapeDialect::Backslash);
+		assert!(parse(&config).0.contains(r#"Str "*n\n""#));
+
+		config.escapes = Some(EscapeDialect::Both);
+		config.standard = Standard::B1969;
+		assert_eq!(parse(&config).1, ["`\\` escapes are an extension to B"]);
+	}
+
+	#[test]
+	fn extensions_must_be_enabled() {
+		let parse_with = |source: &str, extensions: &[Extension]| {
+			let mut config = CompilationConfiguration::for_test(source);
+			config.extensions = extensions.to_vec();
+			parse(&config).1
+		};
+		assert_eq!(
+			parse_with("f() { do x; }", &[]),
+			["expected `;`, found a name"]
+		);
+		assert_eq!(
+			parse_with("f() { do x; while (y); }", &[Extension::DoWhile]),
+			Vec::<String>::new()
+		);
+		assert_eq!(
+			parse_with("f() { for (;;) x; }", &[]),
+			[
+				"expected an expression, found `;`",
+				"expected an expression, found `)`"
+			]
+		);
+		assert_eq!(
+			parse_with("f() { for (;;) x; }", &[Extension::For]),
+			Vec::<String>::new()
+		);
+		assert_eq!(
+			parse_with("a 0x10;", &[]),
+			["hexadecimal literals require `--ext=modern-literals`"]
+		);
+		assert_eq!(
+			parse_with("a 0x10;", &[Extension::ModernLiterals]),
+			Vec::<String>::new()
+		);
+		assert_eq!(
+			parse_with("rec a { b; }", &[]),
+			["expected `;`, found `{`"]
+		);
+		assert_eq!(
+			parse_with("rec a { b; }", &[Extension::Records]),
+			Vec::<String>::new()
+		);
+	}
+
+	#[test]
+	fn octal_constants() {
+		let (ast, messages) = parse_source("a 017, 09, 0, 00, 10;");
+		assert_eq!(messages, Vec::<String>::new());
+		let values: Vec<_> = ast
+			.lines()
+			.filter_map(|line| line.trim().strip_prefix("Int "))
+			.map(|rest| rest.split(' ').next().unwrap())
+			.collect();
+		assert_eq!(values, ["15", "9", "0", "0", "10"]);
+	}
+
+	#[test]
+	fn integer_constant_overflow() {
+		let (_, messages) = parse_source(&format!("a 0{};", "7".repeat(43)));
+		assert_eq!(messages, ["integer constant is too large"]);
+	}
+
+	#[test]
+	fn missing_operands_become_error_expressions() {
+		let (ast, messages) = parse_source("main() { x = ; y = @; }");
+		assert_eq!(
+			ast,
+			"\
+Program test.b
+	Func main [1:1]
+		Expr [1:10]
+			Assign [1:10]
+				Id x [1:10]
+				Error [1:13]
+		Expr [1:16]
+			Assign [1:16]
+				Id y [1:16]
+				Error [1:20]
+"
+		);
+		assert_eq!(
+			messages,
+			[
+				"unrecognized token `@`",
+				"expected an expression, found `;`"
+			]
+		);
+	}
+
+	#[test]
+	fn one_error_per_bad_token() {
+		let (body, messages) = parse_body(") ; f(; g(1;");
+		assert_eq!(
+			body,
+			"\
+Error
+Error
+Error
+"
+		);
+		assert_eq!(
+			messages,
+			[
+				"expected an expression, found `)`",
+				"expected an expression, found `;`",
+				"expected `)`, found `;`",
+			]
+		);
+	}
+
+	#[test]
+	fn nesting_is_limited() {
+		let too_deep = ["statements and expressions are nested too deeply"];
+		let parens = format!("x = {}1{};", "(".repeat(3000), ")".repeat(3000));
+		assert_eq!(parse_body(&parens).1, too_deep);
+		let chain = format!("x = 1{};", " - 1".repeat(3000));
+		assert_eq!(parse_body(&chain).1, too_deep);
+		let calls = format!("x = f{};", "(0)".repeat(3000));
+		assert_eq!(parse_body(&calls).1, too_deep);
+		let negations = format!("x = {}1;", "-".repeat(3000));
+		assert_eq!(parse_body(&negations).1, too_deep);
+
+		let blocks = format!("{}x;{} y;", "{".repeat(3000), "}".repeat(3000));
+		let (body, messages) = parse_body(&blocks);
+		assert_eq!(messages[0], too_deep[0]);
+		assert!(body.ends_with("Expr\n\tId y\n"));
+
+		let (_, messages) = parse_body(&"(".repeat(100));
+		assert_eq!(messages, ["expected an expression, found `}`"]);
+	}
+
+	#[test]
+	fn unterminated_comments_are_reported_once() {
+		let (_, messages) = parse_source("main() { x = 1 /* oops\n}\n");
+		assert_eq!(messages, ["unterminated block comment"]);
+	}
+
+	#[test]
+	fn extensions_are_diagnosed_in_strict_mode() {
+		let mut config = CompilationConfiguration::for_test(
+			"rec r { a; } main() { __asm(\"nop\"); p.a; }",
+		);
+		config.standard = Standard::B1969;
+		config.extensions = vec![Extension::Records, Extension::Asm];
+		let (_, messages) = parse(&config);
+		assert_eq!(
+			messages,
+			[
+				"records are an extension to B",
+				"`__asm` statements are an extension to B",
+				"record member accesses are an extension to B",
+			]
+		);
+
+		config.standard = Standard::Extended;
+		assert_eq!(parse(&config).1, Vec::<String>::new());
+	}
+
+	#[test]
+	fn empty_error_statements_stay_within_their_parent() {
+		for source in ["f() }", "main() { if (x) }", "main() { while (1) }"] {
+			let (_, messages) = parse_source(source);
+			assert!(!messages.is_empty(), "{source:?} parsed without errors");
+		}
+	}
+}
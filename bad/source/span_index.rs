@@ -0,0 +1,261 @@
+//! Lookup of AST nodes by source position.
+//!
+//! Tooling frequently needs to answer "which node is at this offset?"; a
+//! [`SpanIndex`] is built once after parsing so that these queries do not have
+//! to re-walk the tree.
+
+use crate::ast::*;
+
+/// A reference to a definition, statement, or expression.
+#[derive(Copy, Clone)]
+pub enum NodeRef<'ctx> {
+	/// A top-level definition.
+	Def(&'ctx Def<'ctx>),
+	/// A statement.
+	Stmt(&'ctx Stmt<'ctx>),
+	/// An expression.
+	Expr(&'ctx Expr<'ctx>),
+}
+
+/// An index from byte offsets to the AST nodes that enclose them.
+pub struct SpanIndex<'ctx> {
+	// Every node with its byte range, ordered by start offset and, for equal
+	// starts, outermost first. Because spans nest, the nodes enclosing any given
+	// offset appear in this list from outermost to innermost.
+	nodes: Vec<((usize, usize), NodeRef<'ctx>)>,
+	// For each node in `nodes`, the index of the innermost other node that
+	// encloses it, if any.
+	parents: Vec<Option<usize>>,
+}
+
+impl<'ctx> SpanIndex<'ctx> {
+	/// Builds an index over every definition, statement, and expression in
+	/// `program`.
+	///
+	/// Spans are assumed to nest properly; see
+	/// [`check_spans()`](crate::validate::check_spans).
+	pub fn new(program: &Program<'ctx>, ctx: &Context) -> Self {
+		let mut builder = Builder {
+			ctx,
+			nodes: Vec::new(),
+		};
+		for def in program.defs {
+			builder.def(def);
+		}
+
+		let mut nodes = builder.nodes;
+		// This sort is stable, so nodes with identical ranges stay in preorder,
+		// i.e. parents before children.
+		nodes.sort_by_key(|&((start, end), _)| (start, std::cmp::Reverse(end)));
+
+		// Each node's parent is the nearest earlier node that has not ended
+		// before it does.
+		let mut parents = Vec::with_capacity(nodes.len());
+		let mut open: Vec<usize> = Vec::new();
+		for (i, &((_, end), _)) in nodes.iter().enumerate() {
+			while open.last().is_some_and(|&j| {
+				let ((_, open_end), _) = nodes[j];
+				open_end < end
+			}) {
+				open.pop();
+			}
+			parents.push(open.last().copied());
+			open.push(i);
+		}
+		Self { nodes, parents }
+	}
+
+	/// Returns the innermost node whose span contains the byte at `offset`.
+	pub fn innermost(&self, offset: usize) -> Option<NodeRef<'ctx>> {
+		self.innermost_covering((offset, offset + 1))
+	}
+
+	/// Returns the innermost node whose span contains all of `range`.
+	///
+	/// This is a binary search followed by a walk up the enclosing nodes, so it
+	/// takes time logarithmic in the number of nodes plus linear in their
+	/// nesting depth.
+	pub fn innermost_covering(
+		&self,
+		(start, end): (usize, usize),
+	) -> Option<NodeRef<'ctx>> {
+		// The last node starting at or before `start` is nested within every
+		// node that covers `range`, so those are all among its ancestors.
+		let candidates = self.nodes.partition_point(|&((s, _), _)| s <= start);
+		let mut i = candidates.checked_sub(1)?;
+		loop {
+			let ((_, e), node) = self.nodes[i];
+			if end <= e {
+				return Some(node);
+			}
+			i = self.parents[i]?;
+		}
+	}
+}
+
+struct Builder<'a, 'ctx> {
+	ctx: &'a Context,
+	nodes: Vec<((usize, usize), NodeRef<'ctx>)>,
+}
+
+impl<'ctx> Builder<'_, 'ctx> {
+	fn push(&mut self, span: Span, node: NodeRef<'ctx>) {
+		self.nodes.push((span.range(self.ctx), node));
+	}
+
+	fn def(&mut self, def: &'ctx Def<'ctx>) {
+		match def {
+			Def::Global(global) => self.push(global.span, NodeRef::Def(def)),
+			Def::Func(func) => {
+				self.push(func.span, NodeRef::Def(def));
+				for stmt in func.body {
+					self.stmt(stmt);
+				}
+			}
+			Def::Record(record) => self.push(record.span, NodeRef::Def(def)),
+		}
+	}
+
+	fn stmt(&mut self, stmt: &'ctx Stmt<'ctx>) {
+		self.push(stmt.span, NodeRef::Stmt(stmt));
+		match &stmt.kind {
+			StmtKind::Block(stmts) => {
+				for stmt in *stmts {
+					self.stmt(stmt);
+				}
+			}
+			StmtKind::If { cond, body, elze } => {
+				self.expr(cond);
+				self.stmt(body);
+				if let Some(elze) = elze {
+					self.stmt(elze);
+				}
+			}
+			StmtKind::While { cond, body } => {
+				self.expr(cond);
+				self.stmt(body);
+			}
+			StmtKind::DoWhile { body, cond } => {
+				self.stmt(body);
+				self.expr(cond);
+			}
+			StmtKind::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				for expr in [init, cond, step].into_iter().flatten() {
+					self.expr(expr);
+				}
+				self.stmt(body);
+			}
+			StmtKind::Switch { switchee, body } => {
+				self.expr(switchee);
+				self.stmt(body);
+			}
+			StmtKind::Goto(expr) | StmtKind::Expr(expr) => self.expr(expr),
+			StmtKind::Return(expr) => {
+				if let Some(expr) = expr {
+					self.expr(expr);
+				}
+			}
+			StmtKind::Auto { .. }
+			| StmtKind::Extrn { .. }
+			| StmtKind::Label(_)
+			| StmtKind::Case(_)
+			| StmtKind::Asm { .. }
+			| StmtKind::Empty
+			| StmtKind::Error => {}
+		}
+	}
+
+	fn expr(&mut self, expr: &'ctx Expr<'ctx>) {
+		self.push(expr.span, NodeRef::Expr(expr));
+		match &expr.kind {
+			ExprKind::Parens(inner) => self.expr(inner),
+			ExprKind::Deref { ptr } | ExprKind::Member { ptr, .. } => {
+				self.expr(ptr)
+			}
+			ExprKind::Index { ptr, index } => {
+				self.expr(ptr);
+				self.expr(index);
+			}
+			ExprKind::Assign { lhs, rhs }
+			| ExprKind::Binary { lhs, rhs, .. } => {
+				self.expr(lhs);
+				self.expr(rhs);
+			}
+			ExprKind::Unary { expr, .. } => self.expr(expr),
+			ExprKind::Ternary { cond, yes, no } => {
+				self.expr(cond);
+				self.expr(yes);
+				self.expr(no);
+			}
+			ExprKind::Call { func, args } => {
+				self.expr(func);
+				for arg in *args {
+					self.expr(arg);
+				}
+			}
+			ExprKind::InitVal(_) | ExprKind::Error => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lex;
+	use crate::parse;
+	use crate::CompilationConfiguration;
+
+	/// Describes a node as its kind and source text.
+	fn describe(node: Option<NodeRef>, ctx: &Context) -> String {
+		match node {
+			Some(NodeRef::Def(Def::Func(func))) => {
+				format!("func {}", func.name.name)
+			}
+			Some(NodeRef::Def(_)) => "def".to_string(),
+			Some(NodeRef::Stmt(stmt)) => {
+				format!("stmt {}", stmt.span.text(ctx))
+			}
+			Some(NodeRef::Expr(expr)) => {
+				format!("expr {}", expr.span.text(ctx))
+			}
+			None => "none".to_string(),
+		}
+	}
+
+	#[test]
+	fn innermost() {
+		let source = "x 1;\nmain() {\n\tx = a + b * c;\n\tf(x);\n}\n";
+		let config = CompilationConfiguration::for_test(source);
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let tokens = lex::lex(&ctx, &config, &mut Vec::new());
+		let program = parse::parse(tokens, &ctx, &config, &mut Vec::new());
+		let index = SpanIndex::new(&program, &ctx);
+
+		let at = |needle: &str| {
+			let offset = source.find(needle).unwrap();
+			describe(index.innermost(offset), &ctx)
+		};
+		assert_eq!(at("x 1"), "def");
+		assert_eq!(at("main"), "func main");
+		assert_eq!(at("a +"), "expr a");
+		assert_eq!(at("+ b"), "expr a + b * c");
+		assert_eq!(at("* c"), "expr b * c");
+		assert_eq!(at(";\n\tf"), "stmt x = a + b * c;");
+		assert_eq!(at("\tf"), "func main");
+		assert_eq!(at("(x)"), "expr f(x)");
+		assert_eq!(at("}"), "func main");
+		assert_eq!(describe(index.innermost(source.len()), &ctx), "none");
+
+		let a = source.find("a +").unwrap();
+		let c = source.find("c;").unwrap();
+		assert_eq!(
+			describe(index.innermost_covering((a, c + 1)), &ctx),
+			"expr a + b * c"
+		);
+	}
+}
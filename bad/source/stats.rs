@@ -0,0 +1,267 @@
+//! Summary statistics about a lexed source file.
+
+use std::io;
+use std::io::Write;
+
+use crate::ast::*;
+use crate::lex::TokenCategory;
+use crate::lex::TokenList;
+use crate::lex::TokenName;
+
+/// Counts of the tokens and lines in a source file.
+pub struct TokenStats<'ctx> {
+	/// The total number of tokens.
+	pub tokens: usize,
+	/// How many tokens there are in each category that occurs, most common
+	/// first, and otherwise in order of first appearance.
+	pub categories: Vec<(TokenCategory, usize)>,
+	/// How many tokens there are of each kind that occurs, ordered like
+	/// `categories`.
+	pub names: Vec<(TokenName, usize)>,
+	/// The number of lines with any part of a token on them.
+	pub code_lines: usize,
+	/// The number of lines with only comments and whitespace on them.
+	pub comment_lines: usize,
+	/// The number of lines with only whitespace on them.
+	pub blank_lines: usize,
+	/// The longest identifier, by characters; the first, if there is a tie.
+	pub longest_identifier: Option<&'ctx str>,
+	/// The longest literal, by characters and including any quotes; the first,
+	/// if there is a tie.
+	pub longest_literal: Option<&'ctx str>,
+}
+
+impl<'ctx> TokenStats<'ctx> {
+	/// Computes statistics for `tokens`, which were lexed from `ctx`.
+	pub fn new(tokens: &TokenList, ctx: &'ctx Context) -> Self {
+		let lines: Vec<&str> = ctx.source().lines().collect();
+		let mut is_code = vec![false; lines.len()];
+		let mut coords = CoordsCursor::new(ctx);
+		let mut stats = TokenStats {
+			tokens: tokens.tokens.len(),
+			categories: Vec::new(),
+			names: Vec::new(),
+			code_lines: 0,
+			comment_lines: 0,
+			blank_lines: 0,
+			longest_identifier: None,
+			longest_literal: None,
+		};
+
+		for token in &tokens.tokens {
+			count(&mut stats.categories, token.name.categorize());
+			count(&mut stats.names, token.name);
+
+			let text = token.span.text(ctx);
+			let longest = match token.name.categorize() {
+				TokenCategory::Identifier => {
+					Some(&mut stats.longest_identifier)
+				}
+				TokenCategory::Literal => Some(&mut stats.longest_literal),
+				_ => None,
+			};
+			if let Some(longest) = longest {
+				let len = text.chars().count();
+				if longest.is_none_or(|prev| prev.chars().count() < len) {
+					*longest = Some(text);
+				}
+			}
+
+			// Only unterminated comments, which are error tokens, can cross
+			// lines.
+			let first = coords.coords(token.span).0 as usize;
+			let newlines = text.trim_end_matches('\n').matches('\n').count();
+			for line in is_code.iter_mut().skip(first).take(newlines + 1) {
+				*line = true;
+			}
+		}
+
+		for (line, is_code) in lines.iter().zip(is_code) {
+			if is_code {
+				stats.code_lines += 1;
+			} else if line.trim().is_empty() {
+				stats.blank_lines += 1;
+			} else {
+				stats.comment_lines += 1;
+			}
+		}
+
+		// This sort is stable, so ties stay in order of first appearance.
+		stats.categories.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+		stats.names.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+		stats
+	}
+
+	/// Writes these statistics to `out` as indented, human-readable text.
+	pub fn write_text(&self, out: &mut dyn Write) -> io::Result<()> {
+		writeln!(out, "tokens: {}", self.tokens)?;
+		writeln!(out, "categories:")?;
+		for (category, n) in &self.categories {
+			writeln!(out, "\t{:?}: {}", category, n)?;
+		}
+		writeln!(out, "names:")?;
+		for (name, n) in &self.names {
+			writeln!(out, "\t{:?}: {}", name, n)?;
+		}
+		writeln!(
+			out,
+			"lines: {} code, {} comment, {} blank",
+			self.code_lines, self.comment_lines, self.blank_lines
+		)?;
+		for (what, text) in [
+			("identifier", self.longest_identifier),
+			("literal", self.longest_literal),
+		] {
+			match text {
+				Some(text) => writeln!(
+					out,
+					"longest {}: {} ({} characters)",
+					what,
+					text,
+					text.chars().count()
+				)?,
+				None => writeln!(out, "longest {}: none", what)?,
+			}
+		}
+		Ok(())
+	}
+
+	/// Writes these statistics to `out` as a single JSON object.
+	pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+		write!(out, "{{\"tokens\":{},\"categories\":{{", self.tokens)?;
+		for (i, (category, n)) in self.categories.iter().enumerate() {
+			let comma = if i == 0 { "" } else { "," };
+			write!(out, "{}\"{:?}\":{}", comma, category, n)?;
+		}
+		write!(out, "}},\"names\":{{")?;
+		for (i, (name, n)) in self.names.iter().enumerate() {
+			let comma = if i == 0 { "" } else { "," };
+			write!(out, "{}\"{:?}\":{}", comma, name, n)?;
+		}
+		write!(
+			out,
+			"}},\"lines\":{{\"code\":{},\"comment\":{},\"blank\":{}}}",
+			self.code_lines, self.comment_lines, self.blank_lines
+		)?;
+		for (key, text) in [
+			("longest_identifier", self.longest_identifier),
+			("longest_literal", self.longest_literal),
+		] {
+			write!(out, ",\"{}\":", key)?;
+			match text {
+				Some(text) => write_json_string(out, text)?,
+				None => write!(out, "null")?,
+			}
+		}
+		writeln!(out, "}}")
+	}
+}
+
+/// Adds one to the count for `key` in `counts`, adding it if it is new.
+fn count<K: PartialEq>(counts: &mut Vec<(K, usize)>, key: K) {
+	match counts.iter_mut().find(|(k, _)| *k == key) {
+		Some((_, n)) => *n += 1,
+		None => counts.push((key, 1)),
+	}
+}
+
+/// Writes `text` as a quoted JSON string.
+fn write_json_string(out: &mut dyn Write, text: &str) -> io::Result<()> {
+	write!(out, "\"")?;
+	for c in text.chars() {
+		match c {
+			'"' => write!(out, "\\\"")?,
+			'\\' => write!(out, "\\\\")?,
+			'\n' => write!(out, "\\n")?,
+			'\t' => write!(out, "\\t")?,
+			c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+			c => write!(out, "{}", c)?,
+		}
+	}
+	write!(out, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lex;
+	use crate::CompilationConfiguration;
+	use crate::StatsFormat;
+
+	const SOURCE: &str = "\
+// A program.
+main() {
+	extrn printf;
+
+	/* Say
+	 * hello. */
+	printf(\"hi*n\", 'ab', 10);
+}
+";
+
+	/// Writes the statistics for `source` in `format`.
+	fn stats(source: &str, format: StatsFormat) -> String {
+		let config = CompilationConfiguration::for_test(source);
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let tokens = lex::lex(&ctx, &config, &mut Vec::new());
+		let stats = TokenStats::new(&tokens, &ctx);
+		let mut out = Vec::new();
+		match format {
+			StatsFormat::Text => stats.write_text(&mut out).unwrap(),
+			StatsFormat::Json => stats.write_json(&mut out).unwrap(),
+		}
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn text() {
+		assert_eq!(
+			stats(SOURCE, StatsFormat::Text),
+			"\
+tokens: 17
+categories:
+	Punctuation: 10
+	Identifier: 3
+	Literal: 3
+	Keyword: 1
+names:
+	Identifier: 3
+	LeftParen: 2
+	RightParen: 2
+	Semicolon: 2
+	Comma: 2
+	LeftBrace: 1
+	Extrn: 1
+	String: 1
+	Char: 1
+	Number: 1
+	RightBrace: 1
+lines: 4 code, 3 comment, 1 blank
+longest identifier: printf (6 characters)
+longest literal: \"hi*n\" (6 characters)
+"
+		);
+	}
+
+	#[test]
+	fn json() {
+		assert_eq!(
+			stats(SOURCE, StatsFormat::Json),
+			"{\"tokens\":17,\
+			 \"categories\":{\"Punctuation\":10,\"Identifier\":3,\
+			 \"Literal\":3,\"Keyword\":1},\
+			 \"names\":{\"Identifier\":3,\"LeftParen\":2,\"RightParen\":2,\
+			 \"Semicolon\":2,\"Comma\":2,\"LeftBrace\":1,\"Extrn\":1,\
+			 \"String\":1,\"Char\":1,\"Number\":1,\"RightBrace\":1},\
+			 \"lines\":{\"code\":4,\"comment\":3,\"blank\":1},\
+			 \"longest_identifier\":\"printf\",\
+			 \"longest_literal\":\"\\\"hi*n\\\"\"}\n"
+		);
+		assert_eq!(
+			stats("", StatsFormat::Json),
+			"{\"tokens\":0,\"categories\":{},\"names\":{},\
+			 \"lines\":{\"code\":0,\"comment\":0,\"blank\":0},\
+			 \"longest_identifier\":null,\"longest_literal\":null}\n"
+		);
+	}
+}
@@ -0,0 +1,229 @@
+//! Where programs are read from, and where output is written to.
+//!
+//! The compiler only ever sees [`ProgramSource`] and [`ProgramSink`] trait
+//! objects, so embedders can feed it from, and capture its output into,
+//! anything that implements [`Read`] or [`Write`]: files, the standard
+//! streams, in-memory buffers, sockets, or compression adapters.
+
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Stderr, Stdin, Stdout, Write};
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Something a program's source text can be read from.
+pub trait ProgramSource {
+	/// Returns the path this source is reported as in diagnostics.
+	fn path(&self) -> &Path;
+
+	/// Opens this source for reading.
+	fn open(&self) -> io::Result<Box<dyn Read + '_>>;
+}
+
+/// Something compiler output can be written to.
+pub trait ProgramSink {
+	/// Opens this sink for writing.
+	///
+	/// File-backed sinks are created or truncated when opened.
+	fn open(&self) -> io::Result<Box<dyn Write + '_>>;
+}
+
+impl ProgramSource for PathBuf {
+	fn path(&self) -> &Path {
+		self
+	}
+
+	fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+		Ok(Box::new(File::open(self)?))
+	}
+}
+
+impl ProgramSink for PathBuf {
+	fn open(&self) -> io::Result<Box<dyn Write + '_>> {
+		Ok(Box::new(File::create(self)?))
+	}
+}
+
+impl ProgramSource for Stdin {
+	fn path(&self) -> &Path {
+		Path::new("<stdin>")
+	}
+
+	fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+		Ok(Box::new(self.lock()))
+	}
+}
+
+impl ProgramSink for Stdout {
+	fn open(&self) -> io::Result<Box<dyn Write + '_>> {
+		Ok(Box::new(self.lock()))
+	}
+}
+
+impl ProgramSink for Stderr {
+	fn open(&self) -> io::Result<Box<dyn Write + '_>> {
+		Ok(Box::new(self.lock()))
+	}
+}
+
+/// A source whose text is already in memory.
+pub struct MemorySource {
+	/// The path to report the source as.
+	pub path: PathBuf,
+	/// The source text.
+	pub text: String,
+}
+
+impl ProgramSource for MemorySource {
+	fn path(&self) -> &Path {
+		&self.path
+	}
+
+	fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+		Ok(Box::new(self.text.as_bytes()))
+	}
+}
+
+/// A source backed by an arbitrary reader, such as a socket or decompressor.
+///
+/// The reader is consumed as it is read, so it can only usefully be opened
+/// once.
+pub struct StreamSource<R> {
+	path: PathBuf,
+	reader: RefCell<R>,
+}
+
+impl<R: Read> StreamSource<R> {
+	/// Creates a new source that reads from `reader`, reporting it as `path`.
+	pub fn new(path: PathBuf, reader: R) -> Self {
+		Self {
+			path,
+			reader: RefCell::new(reader),
+		}
+	}
+}
+
+impl<R: Read> ProgramSource for StreamSource<R> {
+	fn path(&self) -> &Path {
+		&self.path
+	}
+
+	fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+		let reader = self.reader.try_borrow_mut().map_err(|_| {
+			io::Error::new(io::ErrorKind::WouldBlock, "source is already open")
+		})?;
+		Ok(Box::new(Borrowed(reader)))
+	}
+}
+
+/// A sink backed by an arbitrary writer, such as an in-memory buffer, a socket,
+/// or a compressor.
+///
+/// Output from every time the sink is opened is appended to the same writer.
+pub struct StreamSink<W> {
+	writer: RefCell<W>,
+}
+
+impl<W: Write> StreamSink<W> {
+	/// Creates a new sink that writes to `writer`.
+	pub fn new(writer: W) -> Self {
+		Self {
+			writer: RefCell::new(writer),
+		}
+	}
+
+	/// Consumes this sink, returning the writer (e.g., a buffer holding
+	/// everything written so far).
+	pub fn into_inner(self) -> W {
+		self.writer.into_inner()
+	}
+}
+
+impl<W: Write> ProgramSink for StreamSink<W> {
+	fn open(&self) -> io::Result<Box<dyn Write + '_>> {
+		let writer = self.writer.try_borrow_mut().map_err(|_| {
+			io::Error::new(io::ErrorKind::WouldBlock, "sink is already open")
+		})?;
+		Ok(Box::new(Borrowed(writer)))
+	}
+}
+
+/// Adapts a borrowed reader or writer, since `RefMut` does not forward `Read`
+/// and `Write` itself.
+struct Borrowed<'a, T>(RefMut<'a, T>);
+
+impl<R: Read> Read for Borrowed<'_, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.0.read(buf)
+	}
+}
+
+impl<W: Write> Write for Borrowed<'_, W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn memory_source() {
+		let source = MemorySource {
+			path: "a.b".into(),
+			text: "main() {}".to_string(),
+		};
+		assert_eq!(source.path(), Path::new("a.b"));
+		for _ in 0..2 {
+			let mut text = String::new();
+			source.open().unwrap().read_to_string(&mut text).unwrap();
+			assert_eq!(text, "main() {}");
+		}
+	}
+
+	#[test]
+	fn stream_source() {
+		let source = StreamSource::new("<socket>".into(), &b"abcdef"[..]);
+		assert_eq!(source.path(), Path::new("<socket>"));
+
+		let mut first = source.open().unwrap();
+		let err = source.open().err().expect("opened twice at once");
+		assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+		let mut buf = [0; 2];
+		first.read_exact(&mut buf).unwrap();
+		assert_eq!(&buf, b"ab");
+		drop(first);
+
+		// Reopening picks up where the reader left off.
+		let mut rest = String::new();
+		source.open().unwrap().read_to_string(&mut rest).unwrap();
+		assert_eq!(rest, "cdef");
+	}
+
+	#[test]
+	fn stream_sink() {
+		let sink = StreamSink::new(Vec::new());
+
+		let mut first = sink.open().unwrap();
+		let err = sink.open().err().expect("opened twice at once");
+		assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+		first.write_all(b"one ").unwrap();
+		drop(first);
+
+		// Reopening appends rather than truncating.
+		let mut second = sink.open().unwrap();
+		second.write_all(b"two").unwrap();
+		second.flush().unwrap();
+		drop(second);
+
+		assert_eq!(sink.into_inner(), b"one two");
+	}
+}
@@ -0,0 +1,312 @@
+//! Consistency checks for AST span bookkeeping.
+//!
+//! These are meant to run only in builds with debug assertions enabled; they
+//! catch span bugs where they are introduced, rather than in whatever tool
+//! happens to display the span later.
+
+use crate::ast::*;
+use crate::lex::TokenList;
+
+/// Checks that `tokens` tile the source: each token is non-empty, and they are
+/// in order without overlapping, with nothing but whitespace and comments in
+/// the gaps between them and after the last one.
+///
+/// Panics on the first violation.
+pub fn check_tokens(tokens: &TokenList, ctx: &Context) {
+	let mut prev_end = 0;
+	for token in &tokens.tokens {
+		let (start, end) = token.span.range(ctx);
+		assert!(
+			start < end && end <= ctx.source().len(),
+			"{}: token span {}..{} is empty or out of bounds",
+			token.span.display(ctx),
+			start,
+			end,
+		);
+		assert!(
+			prev_end <= start,
+			"{}: token span {}..{} overlaps the previous token, which ends at \
+			 {}",
+			token.span.display(ctx),
+			start,
+			end,
+			prev_end,
+		);
+		assert!(
+			is_trivia(&ctx.source()[prev_end..start]),
+			"{}: the gap {}..{} before this token is not whitespace or comments",
+			token.span.display(ctx),
+			prev_end,
+			start,
+		);
+		prev_end = end;
+	}
+	assert!(
+		is_trivia(&ctx.source()[prev_end..]),
+		"{}: the text {}.. after the last token is not whitespace or comments",
+		ctx.path().display(),
+		prev_end,
+	);
+}
+
+/// Returns whether `text` consists only of whitespace and comments.
+fn is_trivia(mut text: &str) -> bool {
+	loop {
+		text = text.trim_start_matches([' ', '\t', '\r', '\n', '\x0C']);
+		if text.is_empty() {
+			return true;
+		} else if let Some(rest) = text.strip_prefix("//") {
+			text = rest.find('\n').map_or("", |i| &rest[i..]);
+		} else if let Some(rest) = text.strip_prefix("/*") {
+			match rest.find("*/") {
+				Some(i) => text = &rest[i + 2..],
+				None => return false,
+			}
+		} else {
+			return false;
+		}
+	}
+}
+
+/// Walks `program`, panicking if any node's span lies outside of the source,
+/// is inverted, or is not nested within the span of its parent.
+pub fn check_spans(program: &Program, ctx: &Context) {
+	let checker = SpanChecker { ctx };
+	let file = (0, ctx.source().len());
+	for def in program.defs {
+		checker.def(def, file);
+	}
+}
+
+struct SpanChecker<'a> {
+	ctx: &'a Context,
+}
+
+impl SpanChecker<'_> {
+	/// Checks a single span against its parent's range, and returns its range
+	/// so that it can be used as the parent of the node's children.
+	fn span(&self, span: Span, parent: (usize, usize)) -> (usize, usize) {
+		let (start, end) = span.range(self.ctx);
+		assert!(
+			start <= end && end <= self.ctx.source().len(),
+			"{}: span {}..{} is out of bounds",
+			span.display(self.ctx),
+			start,
+			end,
+		);
+		assert!(
+			parent.0 <= start && end <= parent.1,
+			"{}: span {}..{} is not within its parent {}..{}",
+			span.display(self.ctx),
+			start,
+			end,
+			parent.0,
+			parent.1,
+		);
+		(start, end)
+	}
+
+	fn def(&self, def: &Def, parent: (usize, usize)) {
+		match def {
+			Def::Global(global) => {
+				let range = self.span(global.span, parent);
+				self.span(global.name.span, range);
+				if let Some((size, span)) = &global.size {
+					let size_range = self.span(*span, range);
+					if let ArraySize::Explicit(c) = size {
+						self.constant(c, size_range);
+					}
+				}
+				for init in global.inits {
+					self.init_val(init, range);
+				}
+			}
+			Def::Func(func) => {
+				let range = self.span(func.span, parent);
+				self.span(func.name.span, range);
+				for param in func.params {
+					self.span(param.span, range);
+				}
+				for stmt in func.body {
+					self.stmt(stmt, range);
+				}
+			}
+			Def::Record(record) => {
+				let range = self.span(record.span, parent);
+				self.span(record.name.span, range);
+				for member in record.members {
+					self.span(member.span, range);
+				}
+			}
+		}
+	}
+
+	fn init_val(&self, init: &InitVal, parent: (usize, usize)) {
+		match init {
+			InitVal::Id(id) => {
+				self.span(id.span, parent);
+			}
+			InitVal::Const(c) => self.constant(c, parent),
+		}
+	}
+
+	fn constant(&self, c: &Const, parent: (usize, usize)) {
+		let span = match c {
+			Const::Int(int) => int.span,
+			Const::Char(chr) => chr.span,
+			Const::Str(str) => str.span,
+		};
+		self.span(span, parent);
+	}
+
+	fn stmt(&self, stmt: &Stmt, parent: (usize, usize)) {
+		let range = self.span(stmt.span, parent);
+		match &stmt.kind {
+			StmtKind::Auto { decls } => {
+				for (id, init) in *decls {
+					self.span(id.span, range);
+					if let Some(c) = init {
+						self.constant(c, range);
+					}
+				}
+			}
+			StmtKind::Extrn { decls } => {
+				for id in *decls {
+					self.span(id.span, range);
+				}
+			}
+			StmtKind::Label(id) => {
+				self.span(id.span, range);
+			}
+			StmtKind::Case(c) => self.constant(c, range),
+			StmtKind::Block(stmts) => {
+				for stmt in *stmts {
+					self.stmt(stmt, range);
+				}
+			}
+			StmtKind::If { cond, body, elze } => {
+				self.expr(cond, range);
+				self.stmt(body, range);
+				if let Some(elze) = elze {
+					self.stmt(elze, range);
+				}
+			}
+			StmtKind::While { cond, body } => {
+				self.expr(cond, range);
+				self.stmt(body, range);
+			}
+			StmtKind::DoWhile { body, cond } => {
+				self.stmt(body, range);
+				self.expr(cond, range);
+			}
+			StmtKind::For {
+				init,
+				cond,
+				step,
+				body,
+			} => {
+				for expr in [init, cond, step].into_iter().flatten() {
+					self.expr(expr, range);
+				}
+				self.stmt(body, range);
+			}
+			StmtKind::Switch { switchee, body } => {
+				self.expr(switchee, range);
+				self.stmt(body, range);
+			}
+			StmtKind::Goto(expr) => self.expr(expr, range),
+			StmtKind::Return(expr) => {
+				if let Some(expr) = expr {
+					self.expr(expr, range);
+				}
+			}
+			StmtKind::Asm { code, operands } => {
+				self.span(code.span, range);
+				for id in *operands {
+					self.span(id.span, range);
+				}
+			}
+			StmtKind::Expr(expr) => self.expr(expr, range),
+			StmtKind::Empty | StmtKind::Error => {}
+		}
+	}
+
+	fn expr(&self, expr: &Expr, parent: (usize, usize)) {
+		let range = self.span(expr.span, parent);
+		match &expr.kind {
+			ExprKind::Parens(inner) => self.expr(inner, range),
+			ExprKind::InitVal(init) => self.init_val(init, range),
+			ExprKind::Deref { ptr } => self.expr(ptr, range),
+			ExprKind::Index { ptr, index } => {
+				self.expr(ptr, range);
+				self.expr(index, range);
+			}
+			ExprKind::Member { ptr, member } => {
+				self.expr(ptr, range);
+				self.span(member.span, range);
+			}
+			ExprKind::Assign { lhs, rhs }
+			| ExprKind::Binary { lhs, rhs, .. } => {
+				self.expr(lhs, range);
+				self.expr(rhs, range);
+			}
+			ExprKind::Unary { expr, .. } => self.expr(expr, range),
+			ExprKind::Ternary { cond, yes, no } => {
+				self.expr(cond, range);
+				self.expr(yes, range);
+				self.expr(no, range);
+			}
+			ExprKind::Call { func, args } => {
+				self.expr(func, range);
+				for arg in *args {
+					self.expr(arg, range);
+				}
+			}
+			ExprKind::Error => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lex;
+	use crate::CompilationConfiguration;
+
+	#[test]
+	fn trivia() {
+		assert!(is_trivia(""));
+		assert!(is_trivia(" \t/* a\n * b */ // c\n\n"));
+		assert!(!is_trivia(" x "));
+		assert!(!is_trivia("/* unterminated"));
+	}
+
+	/// Lexes `source`, returning its context and tokens.
+	fn lex_source(source: &str) -> (Context, TokenList) {
+		let config = CompilationConfiguration::for_test(source);
+		let ctx = Context::new("test.b".into(), source.to_string());
+		let tokens = lex::lex(&ctx, &config, &mut Vec::new());
+		(ctx, tokens)
+	}
+
+	#[test]
+	fn lexed_tokens_tile_the_source() {
+		for source in [
+			"/* a */ main() { // b\n x = @ 'c' /*\n*/; }\n",
+			"x; // trailing\n/* comments */\n",
+			"x = 1 /* unterminated",
+			"",
+		] {
+			let (ctx, tokens) = lex_source(source);
+			check_tokens(&tokens, &ctx);
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "after the last token")]
+	fn tokens_must_reach_the_end() {
+		let (ctx, mut tokens) = lex_source("x = 1;\n");
+		tokens.tokens.pop();
+		check_tokens(&tokens, &ctx);
+	}
+}
@@ -1,25 +1,47 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 /// The badc compiler for the B language (Kernighan, 1969). Learning experiment for DrawsMiguel and ThePhD on Rust and some compilation techniques. Released un the CC0 1.0 Universal (e.g. Public Domain dedication).
 #[derive(Parser, Debug)]
 #[command(
 	author,
 	version,
-	about = "A B language (Kernighan, 1969) compiler. Not at all useful."
+	about = "A B language (Kernighan, 1969) compiler. Not at all useful.",
+	args_conflicts_with_subcommands = true
 )]
 struct CommandLineCompilationOptions {
+	#[command(subcommand)]
+	command: Option<Command>,
+
 	/// All of the paths to the input to compile, each one considered an independent translation unit.
 	inputs: Vec<PathBuf>,
 
-	/// Print out the token sequence print out the token representation.
-	#[arg(short, long, default_value_t = true)]
+	/// The language standard to accept: `b1969` or `extended`.
+	#[arg(long = "std", default_value = "extended")]
+	standard: bad::Standard,
+
+	/// Language extensions to enable, e.g. `--ext=for`.
+	#[arg(long = "ext", value_delimiter = ',')]
+	extensions: Vec<bad::Extension>,
+
+	/// The escape character for string and character literals: `star`, `backslash`, or `both` (by default, `star` under `--std=b1969` and `both` otherwise).
+	#[arg(long)]
+	escapes: Option<bad::EscapeDialect>,
+
+	/// Print out the token sequence, to `--print-tokens-output` (by default, the output path with `.badc_tokens` appended).
+	#[arg(short, long)]
 	print_tokens: bool,
 
-	/// Print out an AST representation.
-	#[arg(short, long, default_value_t = true)]
+	/// Print out an AST representation, to `--print-ast-output` (by default, the output path with `.badc_ast` appended).
+	#[arg(short = 'a', long)]
 	print_ast: bool,
 
+	/// Print out counts of tokens and lines, as `text` (the default) or `json`, to `--token-stats-output` (by default, the output path with `.badc_token_stats` appended).
+	#[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+	token_stats: Option<bad::StatsFormat>,
+
 	/// The path to the output.
 	#[arg(short, long)]
 	output: Option<PathBuf>,
@@ -31,19 +53,55 @@ struct CommandLineCompilationOptions {
 	/// The path to the output, specifically for the AST dump.
 	#[arg(long)]
 	print_ast_output: Option<PathBuf>,
+
+	/// The path to the output, specifically for the token statistics.
+	#[arg(long)]
+	token_stats_output: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Create a new project directory containing a `main.b`, which `badc` compiles by default when run inside of it.
+	New {
+		/// The path of the project directory to create. It must not already exist.
+		path: PathBuf,
+	},
+}
+
+/// The `main.b` written into new projects.
+const NEW_PROJECT_MAIN: &str = "\
+main() {
+	extrn printf;
+	printf(\"Hello, World!*n\");
+}
+";
+
+fn new_project(path: &Path) -> io::Result<()> {
+	fs::create_dir_all(path.parent().unwrap_or(Path::new("")))?;
+	fs::create_dir(path)?;
+	fs::write(path.join("main.b"), NEW_PROJECT_MAIN)
 }
 
 fn main() {
 	let mut args = CommandLineCompilationOptions::parse();
+	if let Some(Command::New { path }) = &args.command {
+		if let Err(err) = new_project(path) {
+			eprintln!("{}: {}", path.display(), err);
+			std::process::exit(1);
+		}
+		println!("created {}", path.display());
+		return;
+	}
 	if args.inputs.is_empty() {
 		args.inputs.push(PathBuf::from("./main.b"));
 	}
+	let mut failed = false;
 	for input in args.inputs {
 		let output = match &args.output {
 			Some(target_path) => target_path.clone(),
 			None => {
 				let mut target_path = input.clone();
-				target_path.push(".out");
+				target_path.as_mut_os_string().push(".out");
 				target_path
 			}
 		};
@@ -51,7 +109,7 @@ fn main() {
 			Some(target_path) => target_path.clone(),
 			None => {
 				let mut target_path = output.clone();
-				target_path.push(".badc_tokens");
+				target_path.as_mut_os_string().push(".badc_tokens");
 				target_path
 			}
 		};
@@ -59,19 +117,49 @@ fn main() {
 			Some(target_path) => target_path.clone(),
 			None => {
 				let mut target_path = output.clone();
-				target_path.push(".badc_ast");
+				target_path.as_mut_os_string().push(".badc_ast");
+				target_path
+			}
+		};
+		let token_stats_output = match &args.token_stats_output {
+			Some(target_path) => target_path.clone(),
+			None => {
+				let mut target_path = output.clone();
+				target_path.as_mut_os_string().push(".badc_token_stats");
 				target_path
 			}
 		};
 		let config = bad::CompilationConfiguration {
-			input: bad::ProgramSource::Path(input.clone()),
+			input: Box::new(input.clone()),
+			standard: args.standard,
+			extensions: args.extensions.clone(),
+			escapes: args.escapes,
 			print_tokens: args.print_tokens,
 			print_ast: args.print_ast,
-			output: bad::ProgramSink::Path(output),
-			print_tokens_output: bad::ProgramSink::Path(print_tokens_output),
-			print_ast_output: bad::ProgramSink::Path(print_ast_output),
+			token_stats: args.token_stats,
+			output: Box::new(output),
+			print_tokens_output: Box::new(print_tokens_output),
+			print_ast_output: Box::new(print_ast_output),
+			token_stats_output: Box::new(token_stats_output),
 		};
-		let tree = bad::compile(&config);
-		println!("{:?} 🎉!", tree);
+		match bad::compile(&config) {
+			Ok(result) => {
+				for diagnostic in &result.diagnostics {
+					eprintln!("{}", diagnostic.display(&result.context));
+				}
+				if result.has_errors() {
+					failed = true;
+				} else {
+					println!("{} 🎉!", input.display());
+				}
+			}
+			Err(err) => {
+				eprintln!("{}: {}", input.display(), err);
+				failed = true;
+			}
+		}
+	}
+	if failed {
+		std::process::exit(1);
 	}
 }